@@ -1,2 +0,0 @@
-pub use self::model::Model;
-mod model;
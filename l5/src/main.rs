@@ -1,234 +1,424 @@
 extern crate cgmath;
-extern crate rand;
-extern crate tgaimage_sys;
+#[macro_use]
+extern crate clap;
+extern crate rayon;
+extern crate tinyrenderer;
 
-mod obj;
-use cgmath::InnerSpace;
-use std::ffi::CString;
-use tgaimage_sys as tgaimage;
+use clap::{App, Arg, ArgMatches};
+use std::error::Error;
+use std::process;
+use tinyrenderer::camera;
+use tinyrenderer::gl;
+use tinyrenderer::image;
+use tinyrenderer::light;
+use tinyrenderer::obj;
+use tinyrenderer::scene;
+use tinyrenderer::supersample;
+use tinyrenderer::texture;
+use tinyrenderer::transform;
 
-fn barycentric(
-    points: &Vec<cgmath::Vector3<f64>>,
-    point: cgmath::Vector3<f64>,
-) -> cgmath::Vector3<f64> {
-    let v1 = cgmath::vec3(
-        points[2].x - points[0].x,
-        points[1].x - points[0].x,
-        points[0].x - point.x,
-    );
-    let v2 = cgmath::vec3(
-        points[2].y - points[0].y,
-        points[1].y - points[0].y,
-        points[0].y - point.y,
-    );
-    let u = v1.cross(v2);
-    // degenerate triangle
-    if u.z.abs() < 0. {
-        return cgmath::vec3(-1., 1., 1.);
-    }
-    // convert to floats for a precise result
-    cgmath::vec3(1. - (u.x + u.y) / u.z, u.y / u.z, u.x / u.z)
+// parses a vector written as x,y,z
+fn parse_vector(value: &str) -> Result<cgmath::Vector3<f64>, String> {
+    let values: Vec<&str> = value.split(',').collect();
+    if values.len() != 3 {
+        return Err(format!("expected x,y,z but got '{}'", value));
+    }
+    let mut vector = cgmath::vec3(0., 0., 0.);
+    for (i, value) in values.iter().enumerate() {
+        vector[i] = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid number '{}'", value))?;
+    }
+    Ok(vector)
 }
 
-fn viewport(x: f64, y: f64, w: f64, h: f64, depth: f64) -> cgmath::Matrix4<f64> {
-    cgmath::Matrix4::from_cols(
-        cgmath::vec4(w / 2., 0., 0., 0.),
-        cgmath::vec4(0., h / 2., 0., 0.),
-        cgmath::vec4(0., 0., depth / 2., 0.),
-        cgmath::vec4(x + w / 2., y + h / 2., depth / 2., 1.),
-    )
+fn validate<T: std::str::FromStr>(value: String) -> Result<(), String> {
+    value
+        .parse::<T>()
+        .map(|_| ())
+        .map_err(|_| format!("invalid value '{}'", value))
 }
 
-fn lookat(
-    eye: cgmath::Vector3<f64>,
-    center: cgmath::Vector3<f64>,
-    up: cgmath::Vector3<f64>,
-) -> cgmath::Matrix4<f64> {
-    let z = (eye - center).normalize();
-    let x = up.cross(z).normalize();
-    let y = z.cross(x).normalize();
-
-    cgmath::Matrix4::from_cols(
-        cgmath::vec4(x.x, y.x, z.x, 0.),
-        cgmath::vec4(x.y, y.y, z.y, 0.),
-        cgmath::vec4(x.z, y.z, z.z, 0.),
-        cgmath::vec4(-center.x, -center.y, -center.z, 1.),
-    )
+fn vector_arg<'a, 'b>(name: &'a str, default: &'a str, help: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name("X,Y,Z")
+        .default_value(default)
+        .allow_hyphen_values(true)
+        .validator(|value| parse_vector(&value).map(|_| ()))
+        .help(help)
 }
 
-unsafe fn triangle(
-    points: &Vec<cgmath::Vector3<f64>>,
-    texture_coords: &Vec<cgmath::Vector3<f64>>,
-    zbuffer: &mut Vec<f64>,
-    image: &mut tgaimage::TGAImage,
-    diffuse: &mut tgaimage::TGAImage,
-    normal: &mut tgaimage::TGAImage,
-    light_dir: &cgmath::Vector3<f64>,
-) {
-    let width = image.get_width() as f64;
-    let height = image.get_height() as f64;
-    let mut bounding_box_min = cgmath::vec2(std::f64::MAX, std::f64::MAX);
-    let mut bounding_box_max = cgmath::vec2(std::f64::MIN, std::f64::MIN);
-
-    // use a clamp to keep triangles within max image bounds ( dont draw triangles with coords
-    // outside the image range)
-    let clamp = cgmath::vec2(width - 1., height - 1.);
+fn args<'a>() -> ArgMatches<'a> {
+    App::new("l5")
+        .about("Renders an obj model to a tga image")
+        .arg(
+            Arg::with_name("model")
+                .help("obj file to render, textures come from its mtl materials")
+                .default_value("src/assets/head.obj"),
+        )
+        .arg(
+            Arg::with_name("scene")
+                .long("scene")
+                .value_name("FILE")
+                .conflicts_with_all(&[
+                    "model",
+                    "diffuse",
+                    "normal",
+                    "tangent-normal",
+                    "specular",
+                    "specular-color",
+                    "translate",
+                    "rotate",
+                    "scale",
+                    "ambient",
+                ])
+                .help("toml scene to render instead of a single model"),
+        )
+        .arg(
+            Arg::with_name("camera")
+                .long("camera")
+                .value_name("NAME")
+                .requires("scene")
+                .help("camera of the scene to render from, defaults to the first one"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .default_value("output.tga")
+                .help("tga file to write"),
+        )
+        .arg(
+            Arg::with_name("diffuse")
+                .long("diffuse")
+                .value_name("FILE")
+                .help("diffuse map to use instead of the materials' map_Kd"),
+        )
+        .arg(
+            Arg::with_name("normal")
+                .long("normal")
+                .value_name("FILE")
+                .help("normal map to use instead of the materials' map_Bump"),
+        )
+        .arg(
+            Arg::with_name("tangent-normal")
+                .long("tangent-normal")
+                .value_name("FILE")
+                .conflicts_with("normal")
+                .help("tangent space normal map to use instead of the materials' map_Bump"),
+        )
+        .arg(
+            Arg::with_name("specular")
+                .long("specular")
+                .value_name("FILE")
+                .help("specular exponent map to use instead of the materials' map_Ns"),
+        )
+        .arg(
+            Arg::with_name("specular-color")
+                .long("specular-color")
+                .value_name("R,G,B")
+                .validator(|value| parse_vector(&value).map(|_| ()))
+                .help("color of the highlights instead of the materials' Ks"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("FILTER")
+                .possible_values(&["nearest", "bilinear", "trilinear"])
+                .help("how textures are filtered, nearest unless the scene says otherwise"),
+        )
+        .arg(
+            Arg::with_name("wrap")
+                .long("wrap")
+                .value_name("MODE")
+                .possible_values(&["repeat", "clamp", "mirror"])
+                .help("how uvs outside of [0, 1] wrap, repeat unless the scene says otherwise"),
+        )
+        .arg(
+            Arg::with_name("msaa")
+                .long("msaa")
+                .value_name("SAMPLES")
+                .possible_values(&["1", "2", "4", "8"])
+                .help("samples per pixel for smooth edges, 1 unless the scene says otherwise"),
+        )
+        .arg(
+            Arg::with_name("supersample")
+                .long("supersample")
+                .value_name("FACTOR")
+                .validator(validate::<usize>)
+                .help("render this many times larger in each direction and shrink the result"),
+        )
+        .arg(
+            Arg::with_name("resolve-filter")
+                .long("resolve-filter")
+                .value_name("FILTER")
+                .possible_values(&["box", "tent", "lanczos"])
+                .help("how supersampled images are shrunk, tent unless the scene says otherwise"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .value_name("PIXELS")
+                .default_value("800")
+                .validator(validate::<usize>)
+                .help("width of the image"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .long("height")
+                .value_name("PIXELS")
+                .default_value("800")
+                .validator(validate::<usize>)
+                .help("height of the image"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .value_name("COUNT")
+                .validator(validate::<usize>)
+                .help("how many threads draw the image, one per core by default"),
+        )
+        .arg(vector_arg("translate", "0,0,0", "moves the model"))
+        .arg(vector_arg(
+            "rotate",
+            "0,0,0",
+            "degrees to turn the model around the x, y and z axes",
+        ))
+        .arg(vector_arg(
+            "scale",
+            "1,1,1",
+            "scales the model along each axis",
+        ))
+        .arg(vector_arg("eye", "1,1,3", "camera position"))
+        .arg(vector_arg("target", "0,0,0", "point the camera looks at"))
+        .arg(vector_arg("up", "0,1,0", "camera up direction"))
+        .arg(
+            Arg::with_name("fov")
+                .long("fov")
+                .value_name("DEGREES")
+                .default_value("45")
+                .validator(validate::<f64>)
+                .help("vertical field of view"),
+        )
+        .arg(
+            Arg::with_name("orthographic")
+                .long("orthographic")
+                .help("use a parallel projection instead of a perspective one"),
+        )
+        .arg(vector_arg(
+            "light",
+            "1,1,1",
+            "direction the light comes from",
+        ))
+        .arg(
+            Arg::with_name("ambient")
+                .long("ambient")
+                .value_name("INTENSITY")
+                .default_value("0.1")
+                .validator(validate::<f64>)
+                .help("light that reaches every surface"),
+        )
+        .arg(
+            Arg::with_name("shadows")
+                .long("shadows")
+                .help("render shadow maps for the directional and spot lights"),
+        )
+        .arg(
+            Arg::with_name("shadow-size")
+                .long("shadow-size")
+                .value_name("PIXELS")
+                .validator(validate::<usize>)
+                .help("width and height of the shadow maps, 1024 unless the scene says otherwise"),
+        )
+        .arg(
+            Arg::with_name("shadow-bias")
+                .long("shadow-bias")
+                .value_name("DEPTH")
+                .validator(validate::<f64>)
+                .help("how much closer to the light a point must be to be lit, 0.005 by default"),
+        )
+        .arg(
+            Arg::with_name("shadow-pcf")
+                .long("shadow-pcf")
+                .value_name("TEXELS")
+                .validator(validate::<usize>)
+                .help("softens shadow edges by filtering this far, 0 for hard edges, 1 by default"),
+        )
+        .arg(
+            Arg::with_name("ssao")
+                .long("ssao")
+                .help("darken creases with screen space ambient occlusion"),
+        )
+        .arg(
+            Arg::with_name("ssao-radius")
+                .long("ssao-radius")
+                .value_name("PIXELS")
+                .validator(validate::<usize>)
+                .help("pixels to search for occluders, 16 unless the scene says otherwise"),
+        )
+        .arg(
+            Arg::with_name("ssao-strength")
+                .long("ssao-strength")
+                .value_name("FACTOR")
+                .validator(validate::<f64>)
+                .help("scales the darkening, 1 unless the scene says otherwise"),
+        )
+        .arg(
+            Arg::with_name("ssao-output")
+                .long("ssao-output")
+                .value_name("FILE")
+                .help("also write the ambient occlusion as a grayscale tga file"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .help("print how many faces, triangles and fragments were drawn"),
+        )
+        .get_matches()
+}
 
-    //determine the min/max x and y values to determine the bounds to draw in
-    for i in 0..3 {
-        for j in 0..2 {
-            let _min = points[i][j].min(bounding_box_min[j]);
-            let _max = points[i][j].max(bounding_box_max[j]);
-            bounding_box_min[j] = _min.max(0.0);
-            bounding_box_max[j] = _max.min(clamp[j]);
-        }
+fn main() {
+    if let Err(err) = render(&args()) {
+        eprintln!("error: {}", err);
+        process::exit(1);
     }
+}
 
-    let (x_min, y_min) = (bounding_box_min.x as i32, bounding_box_min.y as i32);
-    let (x_max, y_max) = (bounding_box_max.x as i32 + 1, bounding_box_max.y as i32 + 1);
+fn render(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if args.is_present("threads") {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(value_t!(args, "threads", usize)?)
+            .build_global()?;
+    }
+    // every value has a default and was validated by clap, so these can't fail
+    let light_dir = parse_vector(args.value_of("light").unwrap())?;
+    let eye = parse_vector(args.value_of("eye").unwrap())?;
+    let center = parse_vector(args.value_of("target").unwrap())?;
+    let up = parse_vector(args.value_of("up").unwrap())?;
+    let fov = value_t!(args, "fov", f64)?;
+    let width = value_t!(args, "width", usize)?;
+    let height = value_t!(args, "height", usize)?;
 
-    // check all pixels in the resulting bounding box and color them if they lay within a triangle
-    for x in x_min..x_max {
-        for y in y_min..y_max {
-            let mut point = cgmath::vec3(x as f64, y as f64, 0.);
-            let barycentric_screen = barycentric(points, point);
-            // if any of x,y,z are negative then point is not inside the triangle
-            if barycentric_screen.x < 0. || barycentric_screen.y < 0. || barycentric_screen.z < 0. {
-                continue;
+    let mut scene = match args.value_of("scene") {
+        Some(filename) => scene::Scene::load(filename)?,
+        None => {
+            let mut object =
+                scene::SceneModel::new(obj::Model::load(args.value_of("model").unwrap())?);
+            object.transform = transform::Transform::new(
+                parse_vector(args.value_of("translate").unwrap())?,
+                parse_vector(args.value_of("rotate").unwrap())?,
+                parse_vector(args.value_of("scale").unwrap())?,
+            )
+            .matrix();
+            // textures given on the command line win over the ones the materials name
+            if let Some(filename) = args.value_of("diffuse") {
+                object.diffuse_map = Some(texture::Texture::new(image::Image::read_tga(filename)?));
             }
-
-            // use this to compare to the current value in the zbuffer
-            point.z += points[0].z * barycentric_screen.x + points[1].z * barycentric_screen.y
-                + points[2].z * barycentric_screen.z;
-
-            let index = (point.x + point.y * width) as usize;
-            // draw the point if it is closer to the screen than the current zbuffer value
-            if zbuffer[index] < point.z {
-                zbuffer[index] = point.z;
-
-                // interpolate the vertices w/ barycentric coords to determine the points x,y
-                let mut uv = barycentric_screen.x * texture_coords[0]
-                    + barycentric_screen.y * texture_coords[1]
-                    + barycentric_screen.z * texture_coords[2];
-
-                let mut res = cgmath::vec3(0., 0., 0.);
-                let norm = normal.get(uv.x as i32, uv.y as i32);
-                res.z = norm.bgra[0] as f64 / 255. * 2. - 1.;
-                res.y = norm.bgra[1] as f64 / 255. * 2. - 1.;
-                res.x = norm.bgra[2] as f64 / 255. * 2. - 1.;
-
-                // used to scale pixel brightness, clamp between [0. , 1.]
-                let mut intensity = 0_f64.max(1_f64.min(res.dot(*light_dir)));
-                let mut c = diffuse.get(uv.x as i32, uv.y as i32);
-                for i in 0..3 {
-                    c.bgra[i] = (c.bgra[i] as f64 * intensity) as u8;
-                }
-                //println!("{:?}, {}", c, intensity);
-
-                image.set(x, y, &mut c);
+            if let Some(filename) = args.value_of("normal") {
+                object.normal_map = Some(texture::Texture::new(image::Image::read_tga(filename)?));
             }
-        }
-    }
-}
-
-fn main() {
-    let object = obj::Model::new("src/assets/head.obj");
-    let light_dir = cgmath::vec3(1., 1., 1.).normalize();
-    let eye = cgmath::vec3(1., 1., 3.);
-    let center = cgmath::vec3(0., 0., 0.);
-    let depth = 255.;
-    let width = 800;
-    let height = 800;
-
-    unsafe {
-        let mut image =
-            tgaimage::TGAImage::new1(width, height, tgaimage::TGAImage_Format::RGB as i32);
-        // width/height are 0 because TGAImage_read_tga_file will overwrite the values anyway
-        let mut diffuse = tgaimage::TGAImage::new1(0, 0, tgaimage::TGAImage_Format::RGB as i32);
-        tgaimage::TGAImage_read_tga_file(
-            &mut diffuse,
-            CString::new("src/assets/head_diffuse.tga")
-                .unwrap()
-                .as_ptr(),
-        );
-        diffuse.flip_vertically();
-
-        let mut normal = tgaimage::TGAImage::new1(0, 0, tgaimage::TGAImage_Format::RGB as i32);
-        tgaimage::TGAImage_read_tga_file(
-            &mut normal,
-            CString::new("src/assets/head_nm.tga").unwrap().as_ptr(),
-        );
-        normal.flip_vertically();
-
-        let height = image.get_height() as f64;
-        let width = image.get_width() as f64;
-
-        let mut zbuffer = vec![std::f64::MIN; (width * height) as usize];
-
-        for face in &object.faces {
-            // holds the objects vertex coords manipulated to fit within the image bounds
-            let mut screen_coords: Vec<cgmath::Vector3<f64>> = Vec::new();
-            // the coords of the object as given
-            let mut world_coords: Vec<cgmath::Vector3<f64>> = Vec::new();
-            let mut texture_coords: Vec<cgmath::Vector3<f64>> = Vec::new();
-
-            let (x, y) = (width / 8., height / 8.);
-            let (w, h) = (width * (3. / 4.), height * (3. / 4.));
-
-            // converts the clip coords to x,y screen coordinates and a zbuffer depth
-            let viewport = viewport(x, y, w, h, depth);
-
-            // Model (identity) * View matrix = eye coordinates
-            let model_view = lookat(eye, center, cgmath::vec3(0., 1., 0.));
-
-            let norm = (eye - center).dot(eye - center).sqrt();
-            //perspective deformation matrix, turns eye coords to clip coordinates
-            let projection_matrix = cgmath::Matrix4::from_cols(
-                cgmath::vec4(1., 0., 0., 0.),
-                cgmath::vec4(0., 1., 0., 0.),
-                cgmath::vec4(0., 0., 1., -1. / norm),
-                cgmath::vec4(0., 0., 0., 1.),
-            );
-
-            // resulting transformation matrix
-            let VPMV = viewport * projection_matrix * model_view;
-
-            //let mut intensity : Vec<f64> = Vec::new();
-            for vector in &face.vertices {
-                let vector = *object.get_vertex(*vector);
-                world_coords.push(vector);
-                let projection = VPMV * vector.extend(1.);
-                // project back to 3d by dividing by w and then dropping w
-                let result = (projection / projection.w).truncate();
-                screen_coords.push(result);
+            if let Some(filename) = args.value_of("tangent-normal") {
+                object.normal_map = Some(texture::Texture::new(image::Image::read_tga(filename)?));
+                object.normal_space = obj::NormalSpace::Tangent;
             }
-
-            for indice in &face.texture_indices {
-                let coord = *object.get_texture_coord(*indice);
-                texture_coords.push(cgmath::vec3(
-                    coord.x * (diffuse.get_width() as f64),
-                    coord.y * (diffuse.get_height() as f64),
-                    0.,
-                ));
+            if let Some(color) = args.value_of("specular-color") {
+                object.specular_color = Some(parse_vector(color)?);
             }
-
-            triangle(
-                &screen_coords,
-                &texture_coords,
-                &mut zbuffer,
-                &mut image,
-                &mut diffuse,
-                &mut normal,
-                &light_dir,
-                //intensity,
-            );
+            if let Some(filename) = args.value_of("specular") {
+                object.specular_map =
+                    Some(texture::Texture::new(image::Image::read_tga(filename)?));
+            }
+            let ambient = value_t!(args, "ambient", f64)?;
+            let mut scene = scene::Scene::new();
+            scene.models.push(object);
+            scene.ambient = cgmath::vec3(ambient, ambient, ambient);
+            scene
         }
+    };
+    for object in &scene.models {
+        for warning in &object.model.warnings {
+            eprintln!("warning: {}", warning);
+        }
+    }
+    // the camera and light flags fill in for whatever the scene doesn't have
+    if scene.cameras.is_empty() {
+        let mut camera = camera::Camera::new(eye, center, up, fov, 1.);
+        if args.is_present("orthographic") {
+            camera.projection = camera::Projection::Orthographic;
+        }
+        scene.cameras.push(("0".to_string(), camera));
+    }
+    if scene.lights.is_empty() {
+        scene.lights.push(light::Light::directional(light_dir));
+    }
+    if args.is_present("filter") {
+        scene.sampler.filter = value_t!(args, "filter", texture::Filter)?;
+    }
+    if args.is_present("wrap") {
+        scene.sampler.wrap = value_t!(args, "wrap", texture::Wrap)?;
+    }
+    if args.is_present("msaa") {
+        scene.msaa = value_t!(args, "msaa", gl::Msaa)?;
+    }
+    if args.is_present("supersample") {
+        scene.supersample = Some(supersample::SupersampleSettings {
+            factor: value_t!(args, "supersample", usize)?,
+            ..scene.supersample.unwrap_or_default()
+        });
+    }
+    if args.is_present("resolve-filter") {
+        let filter = value_t!(args, "resolve-filter", supersample::ResolveFilter)?;
+        let settings = scene.supersample.get_or_insert_with(Default::default);
+        settings.filter = filter;
+    }
+    // the shadow flags change whatever the scene has, they only turn shadows on along with
+    // --shadows
+    let mut shadow_settings = scene.shadows.unwrap_or_default();
+    if args.is_present("shadow-size") {
+        shadow_settings.size = value_t!(args, "shadow-size", usize)?;
+    }
+    if args.is_present("shadow-bias") {
+        shadow_settings.bias = value_t!(args, "shadow-bias", f64)?;
+    }
+    if args.is_present("shadow-pcf") {
+        shadow_settings.pcf = value_t!(args, "shadow-pcf", usize)?;
+    }
+    if args.is_present("shadows") || scene.shadows.is_some() {
+        scene.shadows = Some(shadow_settings);
+    }
+
+    let camera = match args.value_of("camera") {
+        Some(name) => *scene
+            .get_camera(name)
+            .ok_or_else(|| format!("the scene has no camera named '{}'", name))?,
+        None => scene.cameras[0].1,
+    };
+    // the ssao flags change whatever the scene has, they only turn ssao on along with --ssao
+    let mut ssao_settings = scene.ssao.unwrap_or_default();
+    if args.is_present("ssao-radius") {
+        ssao_settings.radius = value_t!(args, "ssao-radius", usize)?;
+    }
+    if args.is_present("ssao-strength") {
+        ssao_settings.strength = value_t!(args, "ssao-strength", f64)?;
+    }
+    if args.is_present("ssao") || scene.ssao.is_some() {
+        scene.ssao = Some(ssao_settings);
+    }
 
-        tgaimage::TGAImage_flip_vertically(&mut image);
-        tgaimage::TGAImage_write_tga_file(
-            &mut image,
-            CString::new("output.tga").unwrap().as_ptr(),
-            true,
-        );
+    // the occlusion gets written even when it doesn't darken the image
+    let ssao_output = args.value_of("ssao-output");
+    let frame = scene.render_frame(&camera, width, height, ssao_output.map(|_| &ssao_settings));
+    if let (Some(path), Some(occlusion)) = (ssao_output, &frame.occlusion) {
+        occlusion.write_tga(path, true)?;
+    }
+    if args.is_present("stats") {
+        eprintln!("{}", frame.stats);
     }
+    frame
+        .image
+        .write_tga(args.value_of("output").unwrap(), true)?;
+    Ok(())
 }
@@ -0,0 +1,86 @@
+use cgmath;
+use cgmath::InnerSpace;
+use gl;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // parallel projection, the visible height is the one the perspective projection would show
+    // at the target so switching modes keeps the target framed the same way
+    Orthographic,
+}
+
+// the projection matrices follow the renderer's zbuffer, points on the near plane end up with a
+// depth of 1 and points on the far plane with -1 so closer points are always bigger
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    pub position: cgmath::Vector3<f64>,
+    pub target: cgmath::Vector3<f64>,
+    pub up: cgmath::Vector3<f64>,
+    // vertical field of view in degrees
+    pub fov: f64,
+    // width / height of the image being rendered
+    pub aspect: f64,
+    pub near: f64,
+    pub far: f64,
+    pub projection: Projection,
+}
+
+impl Camera {
+    pub fn new(
+        position: cgmath::Vector3<f64>,
+        target: cgmath::Vector3<f64>,
+        up: cgmath::Vector3<f64>,
+        fov: f64,
+        aspect: f64,
+    ) -> Camera {
+        Camera {
+            position,
+            target,
+            up,
+            fov,
+            aspect,
+            near: 0.1,
+            far: 100.,
+            projection: Projection::Perspective,
+        }
+    }
+
+    // takes world coords to eye coords
+    pub fn view(&self) -> cgmath::Matrix4<f64> {
+        gl::lookat(self.position, self.target, self.up)
+    }
+
+    // takes eye coords to clip coords
+    pub fn projection(&self) -> cgmath::Matrix4<f64> {
+        let (near, far) = (self.near, self.far);
+        let half_height = (self.fov.to_radians() / 2.).tan();
+        match self.projection {
+            Projection::Perspective => {
+                let f = 1. / half_height;
+                cgmath::Matrix4::from_cols(
+                    cgmath::vec4(f / self.aspect, 0., 0., 0.),
+                    cgmath::vec4(0., f, 0., 0.),
+                    cgmath::vec4(0., 0., (far + near) / (far - near), -1.),
+                    cgmath::vec4(0., 0., 2. * far * near / (far - near), 0.),
+                )
+            }
+            Projection::Orthographic => {
+                let distance = (self.target - self.position).magnitude();
+                let top = half_height * distance;
+                let right = top * self.aspect;
+                cgmath::Matrix4::from_cols(
+                    cgmath::vec4(1. / right, 0., 0., 0.),
+                    cgmath::vec4(0., 1. / top, 0., 0.),
+                    cgmath::vec4(0., 0., 2. / (far - near), 0.),
+                    cgmath::vec4(0., 0., (far + near) / (far - near), 1.),
+                )
+            }
+        }
+    }
+
+    // takes world coords to clip coords
+    pub fn transform(&self) -> cgmath::Matrix4<f64> {
+        self.projection() * self.view()
+    }
+}
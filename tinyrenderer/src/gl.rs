@@ -0,0 +1,1038 @@
+use cgmath;
+use cgmath::InnerSpace;
+use image::{Color, Format, Image};
+use obj::Face;
+use rayon::prelude::*;
+use shader::{Derivatives, Shader};
+use std::fmt;
+use std::ops;
+use std::str::FromStr;
+
+pub fn line(mut x0: i32, mut y0: i32, mut x1: i32, mut y1: i32, image: &mut Image, color: Color) {
+    let x_diff = x0 - x1;
+    let y_diff = y0 - y1;
+    let steep = x_diff.abs() < y_diff.abs();
+    // transpose the image
+    if steep {
+        std::mem::swap(&mut x0, &mut y0);
+        std::mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+    }
+
+    let dx = x1 - x0;
+    let dy = y1 - y0;
+    let derror2 = dy.abs() * 2;
+    let mut error2 = 0;
+    let mut y = y0;
+    for x in x0..x1 {
+        if steep {
+            // remove the transpose
+            image.set(x, y, color);
+        } else {
+            image.set(y, x, color);
+        }
+        error2 += derror2;
+        if error2 > dx {
+            y += if y1 > y0 { 1 } else { -1 };
+            error2 -= dx * 2;
+        }
+    }
+}
+
+pub fn viewport(x: f64, y: f64, w: f64, h: f64, depth: f64) -> cgmath::Matrix4<f64> {
+    cgmath::Matrix4::from_cols(
+        cgmath::vec4(w / 2., 0., 0., 0.),
+        cgmath::vec4(0., h / 2., 0., 0.),
+        cgmath::vec4(0., 0., depth / 2., 0.),
+        cgmath::vec4(x + w / 2., y + h / 2., depth / 2., 1.),
+    )
+}
+
+// the view matrix of a camera at eye looking towards center, the camera looks down its -z axis
+pub fn lookat(
+    eye: cgmath::Vector3<f64>,
+    center: cgmath::Vector3<f64>,
+    up: cgmath::Vector3<f64>,
+) -> cgmath::Matrix4<f64> {
+    let z = (eye - center).normalize();
+    let x = up.cross(z).normalize();
+    let y = z.cross(x).normalize();
+
+    cgmath::Matrix4::from_cols(
+        cgmath::vec4(x.x, y.x, z.x, 0.),
+        cgmath::vec4(x.y, y.y, z.y, 0.),
+        cgmath::vec4(x.z, y.z, z.z, 0.),
+        // move the eye to the origin, expressed in the camera's axes
+        cgmath::vec4(-x.dot(eye), -y.dot(eye), -z.dot(eye), 1.),
+    )
+}
+
+// turns barycentric coords found on the screen into barycentric coords of the triangle before
+// the perspective divide, since 1/w is the only thing that changes linearly across the screen
+fn perspective_correct(
+    barycentric_screen: cgmath::Vector3<f64>,
+    clip_w: cgmath::Vector3<f64>,
+) -> cgmath::Vector3<f64> {
+    let barycentric_clip = cgmath::vec3(
+        barycentric_screen.x / clip_w.x,
+        barycentric_screen.y / clip_w.y,
+        barycentric_screen.z / clip_w.z,
+    );
+    barycentric_clip / (barycentric_clip.x + barycentric_clip.y + barycentric_clip.z)
+}
+
+// how far a point in clip coords is inside the near plane, which the camera's projections put
+// at z = w (see camera.rs). anything closer than it would get a depth above 1 and cover
+// everything behind it, and anything behind the eye would be flipped across the screen by the
+// perspective divide
+fn near_distance(point: &cgmath::Vector4<f64>) -> f64 {
+    point.w - point.z
+}
+
+// the same for the far plane at z = -w, whatever is past it would get a depth below -1
+fn far_distance(point: &cgmath::Vector4<f64>) -> f64 {
+    point.w + point.z
+}
+
+// clips the triangle against the near and far planes with sutherland hodgman, before the
+// perspective divide. every corner of the resulting polygon comes with its barycentric coords in
+// the original triangle so the shader's varyings still line up. each plane can add a corner, so
+// what is left has up to five
+fn clip(clip_coords: &[cgmath::Vector4<f64>]) -> Vec<(cgmath::Vector4<f64>, cgmath::Vector3<f64>)> {
+    let mut polygon = vec![
+        (clip_coords[0], cgmath::vec3(1., 0., 0.)),
+        (clip_coords[1], cgmath::vec3(0., 1., 0.)),
+        (clip_coords[2], cgmath::vec3(0., 0., 1.)),
+    ];
+    for distance in &[
+        near_distance as fn(&cgmath::Vector4<f64>) -> f64,
+        far_distance,
+    ] {
+        let corners = polygon;
+        polygon = Vec::with_capacity(corners.len() + 1);
+        for (i, &(current, current_barycentric)) in corners.iter().enumerate() {
+            let (next, next_barycentric) = corners[(i + 1) % corners.len()];
+            let (current_distance, next_distance) = (distance(&current), distance(&next));
+            if current_distance >= 0. {
+                polygon.push((current, current_barycentric));
+            }
+            // the edge crosses the plane, add the point where it does
+            if (current_distance >= 0.) != (next_distance >= 0.) {
+                let t = current_distance / (current_distance - next_distance);
+                polygon.push((
+                    current + (next - current) * t,
+                    current_barycentric + (next_barycentric - current_barycentric) * t,
+                ));
+            }
+        }
+    }
+    polygon
+}
+
+// clips the triangle against the near and far planes and projects what is left to the screen,
+// then walks its bounding box calling the shader's fragment stage with perspective correct
+// barycentric coords for every pixel inside the triangle that is closer to the screen than the
+// current zbuffer value, along with how the coords change towards the next pixels. pixels the
+// shader discards leave the image and zbuffer untouched. returns what became of the triangle
+pub fn triangle<S: Shader>(
+    clip_coords: &[cgmath::Vector4<f64>],
+    viewport: &cgmath::Matrix4<f64>,
+    shader: &S,
+    zbuffer: &mut [f64],
+    image: &mut Image,
+) -> Stats {
+    let (width, height) = (image.get_width(), image.get_height());
+    let mut target = Target {
+        x: 0,
+        y: 0,
+        width: width as i32,
+        height: height as i32,
+        pattern: Msaa::Off.pattern(),
+        zbuffer,
+        image,
+    };
+    let mut stats = Stats::default();
+    let margin = Msaa::Off.margin();
+    for triangle in project(clip_coords, viewport, width, height, margin, &mut stats) {
+        stats.fragments += rasterize(&triangle, shader, &mut target);
+    }
+    stats
+}
+
+// counts what happened to the faces drawn, stats from several draws can be added up
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    // faces passed in
+    pub faces: usize,
+    // faces entirely in front of the near plane or behind the far plane
+    pub clipped: usize,
+    // triangles that were handed to the rasterizer, on the screen or not. clipping against the
+    // near and far planes can cut the corners off a face, leaving up to three triangles
+    pub triangles: usize,
+    // triangles that were skipped for having no area once their corners were snapped to the
+    // subpixel grid, or corners that aren't numbers at all. they couldn't have covered a pixel
+    pub degenerate: usize,
+    // pixels the fragment shader ran for
+    pub fragments: usize,
+}
+
+impl ops::AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.faces += other.faces;
+        self.clipped += other.clipped;
+        self.triangles += other.triangles;
+        self.degenerate += other.degenerate;
+        self.fragments += other.fragments;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} faces, {} clipped, {} triangles, {} degenerate, {} fragments",
+            self.faces, self.clipped, self.triangles, self.degenerate, self.fragments
+        )
+    }
+}
+
+// the width and height of the tiles draw splits the image into
+const TILE_SIZE: usize = 64;
+
+// draws every face with the shader, the same as running the shader's vertex stage on each face
+// and passing it to triangle one after the other but with the rasterizing spread over every
+// thread rayon has. the image is split into tiles, every face goes into the bin of each tile its
+// bounding box touches and the tiles are drawn in parallel, each drawing its bin in the order
+// the faces were given. a pixel only ever sees the faces that cover it in that same order, so
+// the result is exactly what the single threaded triangle would draw
+//
+// every tile runs the vertex stage of its faces again on its own copy of the shader, so the
+// shader's vertex stage can't depend on anything but the face it is given
+pub fn draw<S: Shader + Clone + Sync>(
+    faces: &[Face],
+    shader: &mut S,
+    viewport: &cgmath::Matrix4<f64>,
+    zbuffer: &mut [f64],
+    image: &mut Image,
+) -> Stats {
+    let (width, height) = (image.get_width(), image.get_height());
+    draw_samples(
+        faces,
+        shader,
+        viewport,
+        Msaa::Off,
+        width,
+        height,
+        zbuffer,
+        image,
+    )
+}
+
+// draw with every pixel tested at each sample of the buffer's msaa pattern, see SampleBuffer
+pub fn draw_multisampled<S: Shader + Clone + Sync>(
+    faces: &[Face],
+    shader: &mut S,
+    viewport: &cgmath::Matrix4<f64>,
+    buffer: &mut SampleBuffer,
+) -> Stats {
+    draw_samples(
+        faces,
+        shader,
+        viewport,
+        buffer.msaa,
+        buffer.width,
+        buffer.height,
+        &mut buffer.zbuffer,
+        &mut buffer.samples,
+    )
+}
+
+// draw on a width by height image whose samples are laid out like a SampleBuffer's, every pixel
+// is as many samples wide in the zbuffer and image as the msaa pattern has
+#[allow(clippy::too_many_arguments)]
+fn draw_samples<S: Shader + Clone + Sync>(
+    faces: &[Face],
+    shader: &mut S,
+    viewport: &cgmath::Matrix4<f64>,
+    msaa: Msaa,
+    width: usize,
+    height: usize,
+    zbuffer: &mut [f64],
+    image: &mut Image,
+) -> Stats {
+    let mut stats = Stats::default();
+    let mut triangles = Vec::new();
+    for (index, face) in faces.iter().enumerate() {
+        let clip_coords: Vec<cgmath::Vector4<f64>> =
+            (0..3).map(|nthvert| shader.vertex(face, nthvert)).collect();
+        for triangle in project(
+            &clip_coords,
+            viewport,
+            width,
+            height,
+            msaa.margin(),
+            &mut stats,
+        ) {
+            if triangle.x_min < triangle.x_max && triangle.y_min < triangle.y_max {
+                triangles.push((index, triangle));
+            }
+        }
+    }
+
+    let columns = width.div_ceil(TILE_SIZE);
+    let rows = height.div_ceil(TILE_SIZE);
+    let mut bins = vec![Vec::new(); columns * rows];
+    for (index, (_, triangle)) in triangles.iter().enumerate() {
+        for row in triangle.y_min as usize / TILE_SIZE..=(triangle.y_max - 1) as usize / TILE_SIZE {
+            for column in
+                triangle.x_min as usize / TILE_SIZE..=(triangle.x_max - 1) as usize / TILE_SIZE
+            {
+                bins[column + row * columns].push(index);
+            }
+        }
+    }
+
+    let tiles: Vec<Tile> = {
+        let (zbuffer, image) = (&*zbuffer, &*image);
+        let shader = &*shader;
+        bins.par_iter()
+            .enumerate()
+            .filter(|&(_, bin)| !bin.is_empty())
+            .map(|(index, bin)| {
+                let mut tile = Tile::new(
+                    (index % columns * TILE_SIZE) as i32,
+                    (index / columns * TILE_SIZE) as i32,
+                    width,
+                    height,
+                    msaa,
+                    zbuffer,
+                    image,
+                );
+                let mut shader = shader.clone();
+                let mut last_face = None;
+                for &index in bin {
+                    let (face, ref triangle) = triangles[index];
+                    // clipping can split a face into several, its vertex stage only has to run once
+                    if last_face != Some(face) {
+                        for nthvert in 0..3 {
+                            shader.vertex(&faces[face], nthvert);
+                        }
+                        last_face = Some(face);
+                    }
+                    tile.fragments += rasterize(triangle, &shader, &mut tile.target());
+                }
+                tile
+            })
+            .collect()
+    };
+    for tile in &tiles {
+        tile.copy_to(width, zbuffer, image);
+        stats.fragments += tile.fragments;
+    }
+    stats
+}
+
+// how many samples multisampling tests each pixel at, for smooth edges without running the
+// fragment stage any more often. the samples are where direct3d puts them, which spreads them
+// out so that no two share a row or column
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Msaa {
+    // a single sample at the pixel's center
+    #[default]
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Msaa {
+    // returns None for counts without a sample pattern
+    pub fn from_samples(samples: usize) -> Option<Msaa> {
+        match samples {
+            1 => Some(Msaa::Off),
+            2 => Some(Msaa::X2),
+            4 => Some(Msaa::X4),
+            8 => Some(Msaa::X8),
+            _ => None,
+        }
+    }
+
+    pub fn samples(self) -> usize {
+        self.pattern().len()
+    }
+
+    // where each sample sits relative to the pixel's center in 1/16ths of a pixel, flipped from
+    // direct3d's since y grows upwards here
+    fn pattern(self) -> &'static [(i64, i64)] {
+        match self {
+            Msaa::Off => &[(0, 0)],
+            Msaa::X2 => &[(4, -4), (-4, 4)],
+            Msaa::X4 => &[(-2, 6), (6, 2), (-6, -2), (2, -6)],
+            Msaa::X8 => &[
+                (1, 3),
+                (-1, -3),
+                (5, -1),
+                (-3, 5),
+                (-5, -5),
+                (-7, 1),
+                (3, -7),
+                (7, 7),
+            ],
+        }
+    }
+
+    // how far the samples reach from the pixel's center in fixed point, a triangle covers the
+    // pixel when its corners come this close
+    fn margin(self) -> i64 {
+        self.pattern()
+            .iter()
+            .map(|&(x, y)| x.abs().max(y.abs()) * SUBPIXEL / 16)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl FromStr for Msaa {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Msaa, String> {
+        value
+            .parse()
+            .ok()
+            .and_then(Msaa::from_samples)
+            .ok_or_else(|| format!("no msaa pattern with '{}' samples", value))
+    }
+}
+
+// an image and zbuffer holding every sample of each pixel, for draw_multisampled. the fragment
+// stage runs once for each pixel a triangle covers and its color goes to every sample the
+// triangle covers and is closest at, so edges blend once the samples are resolved
+pub struct SampleBuffer {
+    width: usize,
+    height: usize,
+    msaa: Msaa,
+    // the samples of a pixel sit next to each other, in the order of the pattern
+    zbuffer: Vec<f64>,
+    samples: Image,
+}
+
+impl SampleBuffer {
+    // a cleared buffer, black and infinitely far away
+    pub fn new(width: usize, height: usize, format: Format, msaa: Msaa) -> SampleBuffer {
+        SampleBuffer {
+            width,
+            height,
+            msaa,
+            zbuffer: vec![f64::MIN; width * height * msaa.samples()],
+            samples: Image::new(width * msaa.samples(), height, format),
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_msaa(&self) -> Msaa {
+        self.msaa
+    }
+
+    // averages the samples of each pixel into the final image
+    pub fn resolve(&self) -> Image {
+        let count = self.msaa.samples();
+        let mut image = Image::new(self.width, self.height, self.samples.get_format());
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let mut sum = [0usize; 4];
+                for sample in 0..count as i32 {
+                    let color = self.samples.get(x * count as i32 + sample, y);
+                    for (total, channel) in sum.iter_mut().zip(color.bgra.iter()) {
+                        *total += *channel as usize;
+                    }
+                }
+                let mut color = Color::default();
+                for (channel, total) in color.bgra.iter_mut().zip(sum.iter()) {
+                    *channel = ((total + count / 2) / count) as u8;
+                }
+                image.set(x, y, color);
+            }
+        }
+        image
+    }
+
+    // one depth per pixel, the closest of its samples so the edges of whatever is in front
+    // don't pick up the depth of what is behind them
+    pub fn resolve_depth(&self) -> Vec<f64> {
+        self.zbuffer
+            .chunks(self.msaa.samples())
+            .map(|samples| samples.iter().cloned().fold(f64::MIN, f64::max))
+            .collect()
+    }
+}
+
+// how many bits of fixed point precision the corners of triangles are snapped to, 8 puts them
+// on a grid of 1/256th of a pixel
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL: i64 = 1 << SUBPIXEL_BITS;
+// how far from the image, in pixels, corners are kept. anything further out is off by more than
+// a pixel in an f64 anyway, and keeping them close keeps the edge functions from overflowing
+const MAX_COORD: f64 = (1u64 << 40) as f64;
+
+// the edge function of one side of a triangle, a*x + b*y + c at the pixel x, y is twice the area
+// of the triangle the side makes with the pixel's center. it is positive on the inside of the
+// side, everything is counter clockwise after project, and grows by a for every step to the
+// right and by b for every step up. done in integers on the snapped corners the value is exact,
+// so neighbouring triangles always agree on which side of a shared edge a pixel is on
+#[derive(Copy, Clone, Debug)]
+struct Edge {
+    a: i128,
+    b: i128,
+    c: i128,
+    // pixels exactly on an edge only belong to the triangle the edge is a top or left edge of,
+    // so ones on an edge shared by two triangles are drawn exactly once. the bias is 0 for top
+    // and left edges and -1 for the rest, moving the edge off of them by the smallest step there
+    // is
+    bias: i128,
+}
+
+impl Edge {
+    // the edge from one corner to the next in fixed point coords
+    fn new(from: (i64, i64), to: (i64, i64)) -> Edge {
+        let (dx, dy) = ((to.0 - from.0) as i128, (to.1 - from.1) as i128);
+        // y grows upwards, so going counter clockwise top edges run straight to the left and
+        // left edges run down
+        let top_left = dy < 0 || (dy == 0 && dx < 0);
+        Edge {
+            a: -dy * SUBPIXEL as i128,
+            b: dx * SUBPIXEL as i128,
+            c: dy * from.0 as i128 - dx * from.1 as i128,
+            bias: if top_left { 0 } else { -1 },
+        }
+    }
+
+    fn at(&self, x: i32, y: i32) -> i128 {
+        self.a * x as i128 + self.b * y as i128 + self.c
+    }
+}
+
+// a triangle on the screen, ready to be rasterized
+struct ScreenTriangle {
+    // the clip coords of each corner along with its barycentric coords in the triangle the
+    // shader saw, which only differ from the corners of the triangle when it was clipped
+    corners: [(cgmath::Vector4<f64>, cgmath::Vector3<f64>); 3],
+    // the depth of each corner
+    depths: cgmath::Vector3<f64>,
+    // the edge opposite each corner, the barycentric coord of a corner is its edge function
+    // over the whole triangle's
+    edges: [Edge; 3],
+    // twice the area of the triangle in fixed point
+    area: f64,
+    // the pixels the triangle's bounding box covers in the image, the max is exclusive
+    x_min: i32,
+    y_min: i32,
+    x_max: i32,
+    y_max: i32,
+}
+
+// clips the triangle against the near and far planes and projects what is left to the screen of
+// the given size, clipping a triangle leaves at most a pentagon which is split back up into a
+// fan. the corners are snapped to the subpixel grid and triangles left without any area on it
+// are degenerate and dropped, as are triangles with corners that aren't numbers. what became of
+// the triangle is counted in stats. the bounding box takes in every pixel with a sample within
+// margin of the triangle
+//
+// degenerate triangles can't cover any sample, so dropping them draws exactly what rasterizing
+// them would. triangles with any area at all are kept however thin they are, the edge functions
+// are exact so a sliver gets every sample it covers and no others
+fn project(
+    clip_coords: &[cgmath::Vector4<f64>],
+    viewport: &cgmath::Matrix4<f64>,
+    width: usize,
+    height: usize,
+    margin: i64,
+    stats: &mut Stats,
+) -> Vec<ScreenTriangle> {
+    stats.faces += 1;
+    // a NaN corner would be clipped and snapped as if it were somewhere, and an infinite one
+    // turns into NaNs on the way to the screen
+    let finite = |point: &cgmath::Vector4<f64>| {
+        point.x.is_finite() && point.y.is_finite() && point.z.is_finite() && point.w.is_finite()
+    };
+    if !clip_coords.iter().all(finite) {
+        stats.degenerate += 1;
+        return Vec::new();
+    }
+    let polygon = clip(clip_coords);
+    if polygon.is_empty() {
+        stats.clipped += 1;
+        return Vec::new();
+    }
+    (1..polygon.len() - 1)
+        .filter_map(|i| {
+            let mut corners = [polygon[0], polygon[i], polygon[i + 1]];
+            // project back to 3d by dividing by w and then dropping w
+            let mut points = [cgmath::vec3(0., 0., 0.); 3];
+            for (point, &(clip, _)) in points.iter_mut().zip(&corners) {
+                let screen = viewport * clip;
+                *point = (screen / screen.w).truncate();
+            }
+            // huge but finite coords can still overflow on the way
+            if !points
+                .iter()
+                .all(|point| point.x.is_finite() && point.y.is_finite() && point.z.is_finite())
+            {
+                stats.degenerate += 1;
+                return None;
+            }
+            let snap =
+                |value: f64| (value.clamp(-MAX_COORD, MAX_COORD) * SUBPIXEL as f64).round() as i64;
+            let mut fixed: Vec<(i64, i64)> = points
+                .iter()
+                .map(|point| (snap(point.x), snap(point.y)))
+                .collect();
+
+            let area = (fixed[1].0 - fixed[0].0) as i128 * (fixed[2].1 - fixed[0].1) as i128
+                - (fixed[1].1 - fixed[0].1) as i128 * (fixed[2].0 - fixed[0].0) as i128;
+            if area == 0 {
+                stats.degenerate += 1;
+                return None;
+            }
+            stats.triangles += 1;
+            // faces are drawn whichever way they wind, clockwise ones are turned around
+            if area < 0 {
+                corners.swap(1, 2);
+                points.swap(1, 2);
+                fixed.swap(1, 2);
+            }
+
+            // the pixels whose samples the snapped corners span, clamped to the image
+            let x_min = fixed.iter().map(|p| p.0).min().unwrap() - margin;
+            let x_max = fixed.iter().map(|p| p.0).max().unwrap() + margin;
+            let y_min = fixed.iter().map(|p| p.1).min().unwrap() - margin;
+            let y_max = fixed.iter().map(|p| p.1).max().unwrap() + margin;
+            let first_pixel = |value: i64, size: usize| {
+                ((value + SUBPIXEL - 1).div_euclid(SUBPIXEL)).clamp(0, size as i64) as i32
+            };
+            let last_pixel = |value: i64, size: usize| {
+                (value.div_euclid(SUBPIXEL) + 1).clamp(0, size as i64) as i32
+            };
+
+            Some(ScreenTriangle {
+                corners,
+                depths: cgmath::vec3(points[0].z, points[1].z, points[2].z),
+                edges: [
+                    Edge::new(fixed[1], fixed[2]),
+                    Edge::new(fixed[2], fixed[0]),
+                    Edge::new(fixed[0], fixed[1]),
+                ],
+                area: area.abs() as f64,
+                x_min: first_pixel(x_min, width),
+                y_min: first_pixel(y_min, height),
+                x_max: last_pixel(x_max, width),
+                y_max: last_pixel(y_max, height),
+            })
+        })
+        .collect()
+}
+
+// the part of the image and zbuffer a triangle is drawn into, x and y are where the part starts
+// in the whole image. every pixel is as many samples wide in the zbuffer and image as there are
+// in the pattern
+struct Target<'a> {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    pattern: &'static [(i64, i64)],
+    zbuffer: &'a mut [f64],
+    image: &'a mut Image,
+}
+
+// a copy of one tile of the image and zbuffer, for drawing it on its own thread
+struct Tile {
+    x: i32,
+    y: i32,
+    width: usize,
+    height: usize,
+    msaa: Msaa,
+    zbuffer: Vec<f64>,
+    image: Image,
+    // how many times the fragment stage ran for the tile
+    fragments: usize,
+}
+
+impl Tile {
+    // copies the tile starting at x, y out of an image of full_width by full_height pixels laid
+    // out for msaa, tiles along the right and top edges are cut short
+    fn new(
+        x: i32,
+        y: i32,
+        full_width: usize,
+        full_height: usize,
+        msaa: Msaa,
+        zbuffer: &[f64],
+        image: &Image,
+    ) -> Tile {
+        let samples = msaa.samples();
+        let width = TILE_SIZE.min(full_width - x as usize);
+        let height = TILE_SIZE.min(full_height - y as usize);
+        let mut tile = Tile {
+            x,
+            y,
+            width,
+            height,
+            msaa,
+            zbuffer: Vec::with_capacity(width * height * samples),
+            image: Image::new(width * samples, height, image.get_format()),
+            fragments: 0,
+        };
+        let (x, row) = (x as usize * samples, width * samples);
+        for j in 0..height {
+            let start = x + (y as usize + j) * full_width * samples;
+            tile.zbuffer.extend_from_slice(&zbuffer[start..start + row]);
+            for i in 0..row {
+                tile.image
+                    .set(i as i32, j as i32, image.get((x + i) as i32, y + j as i32));
+            }
+        }
+        tile
+    }
+
+    fn target(&mut self) -> Target<'_> {
+        Target {
+            x: self.x,
+            y: self.y,
+            width: self.width as i32,
+            height: self.height as i32,
+            pattern: self.msaa.pattern(),
+            zbuffer: &mut self.zbuffer,
+            image: &mut self.image,
+        }
+    }
+
+    // puts the tile back where it came from
+    fn copy_to(&self, full_width: usize, zbuffer: &mut [f64], image: &mut Image) {
+        let samples = self.msaa.samples();
+        let (x, row) = (self.x as usize * samples, self.width * samples);
+        for j in 0..self.height {
+            let start = x + (self.y as usize + j) * full_width * samples;
+            zbuffer[start..start + row].copy_from_slice(&self.zbuffer[j * row..(j + 1) * row]);
+            for i in 0..row {
+                image.set(
+                    (x + i) as i32,
+                    self.y + j as i32,
+                    self.image.get(i as i32, j as i32),
+                );
+            }
+        }
+    }
+}
+
+// walks the part of the triangle's bounding box inside the target, stepping the edge functions
+// from pixel to pixel. a pixel is drawn when the triangle covers any of its samples and is
+// closer than what the zbuffer holds there, the fragment stage runs once at the pixel's center
+// and its color goes to those samples. returns how many times the fragment stage ran
+fn rasterize<S: Shader>(triangle: &ScreenTriangle, shader: &S, target: &mut Target) -> usize {
+    let corners = &triangle.corners;
+    let edges = &triangle.edges;
+    // w is kept around so attributes can be interpolated in clip space, interpolating them with
+    // the screen space barycentric coords would warp textures on faces seen at an angle
+    let clip_w = cgmath::vec3(corners[0].0.w, corners[1].0.w, corners[2].0.w);
+
+    // takes the edge functions at a pixel to barycentric coords of the triangle the shader saw,
+    // mapping back from the clipped triangle is a no-op unless it was clipped
+    let to_clip = |values: [i128; 3]| {
+        let barycentric_screen = cgmath::vec3(
+            values[0] as f64 / triangle.area,
+            values[1] as f64 / triangle.area,
+            values[2] as f64 / triangle.area,
+        );
+        let barycentric_clip = perspective_correct(barycentric_screen, clip_w);
+        corners[0].1 * barycentric_clip.x
+            + corners[1].1 * barycentric_clip.y
+            + corners[2].1 * barycentric_clip.z
+    };
+
+    // how much each edge function changes from a pixel's center to each of its samples, the
+    // edges step by a whole pixel at a time and the samples sit on 1/16ths of one
+    let mut offsets = [[0i128; 3]; 8];
+    for (offset, &(x, y)) in offsets.iter_mut().zip(target.pattern) {
+        for (value, edge) in offset.iter_mut().zip(edges) {
+            *value = (edge.a * x as i128 + edge.b * y as i128) / 16;
+        }
+    }
+    let offsets = &offsets[..target.pattern.len()];
+
+    let (x_min, y_min) = (triangle.x_min.max(target.x), triangle.y_min.max(target.y));
+    let x_max = triangle.x_max.min(target.x + target.width);
+    let y_max = triangle.y_max.min(target.y + target.height);
+    if x_min >= x_max {
+        return 0;
+    }
+
+    let mut fragments = 0;
+    // the depth of each sample the pixel is drawn at, None for the ones it isn't
+    let mut depths = [None; 8];
+    for y in y_min..y_max {
+        let mut values = [
+            edges[0].at(x_min, y),
+            edges[1].at(x_min, y),
+            edges[2].at(x_min, y),
+        ];
+        for x in x_min..x_max {
+            let current = values;
+            for (value, edge) in values.iter_mut().zip(edges) {
+                *value += edge.a;
+            }
+
+            let pixel = ((x - target.x) + (y - target.y) * target.width) as usize * offsets.len();
+            let mut covered = false;
+            for (sample, offset) in offsets.iter().enumerate() {
+                depths[sample] = None;
+                let values = [
+                    current[0] + offset[0],
+                    current[1] + offset[1],
+                    current[2] + offset[2],
+                ];
+                // the sample is inside when it is on the inner side of all three edges
+                if values
+                    .iter()
+                    .zip(edges)
+                    .any(|(value, edge)| value + edge.bias < 0)
+                {
+                    continue;
+                }
+                let barycentric_screen = cgmath::vec3(
+                    values[0] as f64 / triangle.area,
+                    values[1] as f64 / triangle.area,
+                    values[2] as f64 / triangle.area,
+                );
+                // use this to compare to the current value in the zbuffer
+                let z = triangle.depths.dot(barycentric_screen);
+                // draw the sample if it is closer to the screen than the current zbuffer value
+                if target.zbuffer[pixel + sample] < z {
+                    depths[sample] = Some(z);
+                    covered = true;
+                }
+            }
+            if !covered {
+                continue;
+            }
+
+            let barycentric_clip = to_clip(current);
+            // the neighbouring pixels don't have to be inside the triangle, the edge functions
+            // carry on outside of it. neither does the center of a pixel only some of whose
+            // samples are covered
+            let derivatives = Derivatives {
+                dx: to_clip(values) - barycentric_clip,
+                dy: to_clip([
+                    current[0] + edges[0].b,
+                    current[1] + edges[1].b,
+                    current[2] + edges[2].b,
+                ]) - barycentric_clip,
+            };
+            fragments += 1;
+            if let Some(color) = shader.fragment(barycentric_clip, derivatives) {
+                for (sample, depth) in depths[..offsets.len()].iter().enumerate() {
+                    if let Some(z) = *depth {
+                        target.zbuffer[pixel + sample] = z;
+                        target.image.set(
+                            (x - target.x) * offsets.len() as i32 + sample as i32,
+                            y - target.y,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    fragments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Format;
+
+    // draws the corners it is given as clip coords and colors every pixel by its barycentric
+    // coords, so any difference in what was drawn where shows up in the image
+    #[derive(Clone)]
+    struct TestShader {
+        corners: Vec<cgmath::Vector4<f64>>,
+        // every face gets its own depth, closer than all of the faces before it
+        layered: bool,
+    }
+
+    impl Shader for TestShader {
+        fn vertex(&mut self, face: &Face, nthvert: usize) -> cgmath::Vector4<f64> {
+            let mut corner = self.corners[face.vertices[nthvert]];
+            if self.layered {
+                corner.z = face.vertices[1] as f64 / self.corners.len() as f64;
+            }
+            corner
+        }
+
+        fn fragment(&self, barycentric: cgmath::Vector3<f64>, _: Derivatives) -> Option<Color> {
+            let color = barycentric * 255.;
+            Some(Color::new(color.x as u8, color.y as u8, color.z as u8, 255))
+        }
+    }
+
+    // a closed fan of count faces around a pixel off the center of a width by height screen.
+    // the corners around the outside are at jittered angles and distances, all further out than
+    // the corners of the screen, and at jittered depths. every corner is on a pixel center and
+    // the fan starts straight to the right, so plenty of the shared edges run through pixel
+    // centers
+    fn fan(
+        count: usize,
+        seed: u64,
+        width: usize,
+        height: usize,
+    ) -> (Vec<cgmath::Vector4<f64>>, Vec<Face>) {
+        let mut state = seed;
+        let mut random = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        // from a pixel to clip coords
+        let (width, height) = (width as f64, height as f64);
+        let clip =
+            |x: f64, y: f64, z: f64| cgmath::vec4(x * 2. / width - 1., y * 2. / height - 1., z, 1.);
+        let center = ((width * 0.45).round(), (height * 0.55).round());
+        let mut corners = vec![clip(center.0, center.1, 0.)];
+        for i in 0..count {
+            let jitter = if i == 0 { 0. } else { random() * 0.5 };
+            let angle = (i as f64 + jitter) / count as f64 * 2. * std::f64::consts::PI;
+            let radius = (width + height) * (2. + random());
+            corners.push(clip(
+                (center.0 + angle.cos() * radius).round(),
+                (center.1 + angle.sin() * radius).round(),
+                random() * 2. - 1.,
+            ));
+        }
+        let faces = (0..count)
+            .map(|i| Face::new(vec![0, i + 1, (i + 1) % count + 1], vec![], vec![], None))
+            .collect();
+        (corners, faces)
+    }
+
+    fn draw_serially(
+        faces: &[Face],
+        shader: &mut TestShader,
+        viewport: &cgmath::Matrix4<f64>,
+        zbuffer: &mut [f64],
+        image: &mut Image,
+    ) -> Stats {
+        let mut stats = Stats::default();
+        for face in faces {
+            let clip_coords: Vec<cgmath::Vector4<f64>> =
+                (0..3).map(|nthvert| shader.vertex(face, nthvert)).collect();
+            stats += triangle(&clip_coords, viewport, shader, zbuffer, image);
+        }
+        stats
+    }
+
+    #[test]
+    fn shared_edges_are_covered_exactly_once() {
+        let (width, height) = (97, 83);
+        let viewport = viewport(0., 0., width as f64, height as f64, 255.);
+        for &count in &[4, 36, 37, 500] {
+            let (corners, faces) = fan(count, count as u64, width, height);
+            let mut shader = TestShader {
+                corners,
+                layered: true,
+            };
+            let mut zbuffer = vec![f64::MIN; width * height];
+            let mut image = Image::new(width, height, Format::RGB);
+            let stats = draw_serially(&faces, &mut shader, &viewport, &mut zbuffer, &mut image);
+
+            // every face is closer than the ones before, so every pixel a face covers runs the
+            // fragment stage. a pixel on a shared edge drawn twice would run it twice and one
+            // that fell through a crack wouldn't run it at all
+            let covered = zbuffer.iter().filter(|&&z| z != f64::MIN).count();
+            assert_eq!(covered, width * height, "{} faces left cracks", count);
+            assert_eq!(
+                stats.fragments, covered,
+                "{} faces drew pixels twice",
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn faces_are_clipped_to_the_near_and_far_planes() {
+        let (width, height) = (64, 64);
+        let viewport = viewport(0., 0., width as f64, height as f64, 255.);
+        // reaches from past the far plane to past the near plane, unclipped its depth would run
+        // from -3 to 3
+        let mut shader = TestShader {
+            corners: vec![
+                cgmath::vec4(-0.9, -0.9, -3., 1.),
+                cgmath::vec4(0.9, -0.9, 3., 1.),
+                cgmath::vec4(0., 0.9, 0., 1.),
+            ],
+            layered: false,
+        };
+        let faces = vec![Face::new(vec![0, 1, 2], vec![], vec![], None)];
+        let mut zbuffer = vec![f64::MIN; width * height];
+        let mut image = Image::new(width, height, Format::RGB);
+        let stats = draw_serially(&faces, &mut shader, &viewport, &mut zbuffer, &mut image);
+
+        // each plane cuts a corner off, leaving a pentagon
+        assert_eq!(stats.triangles, 3);
+        let depths: Vec<f64> = zbuffer.into_iter().filter(|&z| z != f64::MIN).collect();
+        assert_eq!(depths.len(), stats.fragments);
+        assert!(!depths.is_empty());
+        assert!(depths.iter().all(|&z| (0. ..=255.).contains(&z)));
+    }
+
+    #[test]
+    fn draw_matches_triangle() {
+        // not a multiple of the tile size, so the tiles along the right and top are cut short
+        let (width, height) = (TILE_SIZE * 3 + 17, TILE_SIZE * 2 + 5);
+        let viewport = viewport(0., 0., width as f64, height as f64, 255.);
+        // two fans on top of each other at different depths, so faces from both end up in
+        // front in different places
+        let (mut corners, mut faces) = fan(37, 1, width, height);
+        let (more_corners, more_faces) = fan(200, 2, width, height);
+        let offset = corners.len();
+        corners.extend(more_corners);
+        faces.extend(more_faces.into_iter().map(|face| {
+            let vertices = face.vertices.iter().map(|&v| v + offset).collect();
+            Face::new(vertices, vec![], vec![], None)
+        }));
+        let mut shader = TestShader {
+            corners,
+            layered: false,
+        };
+
+        let mut serial_zbuffer = vec![f64::MIN; width * height];
+        let mut serial_image = Image::new(width, height, Format::RGB);
+        let serial_stats = draw_serially(
+            &faces,
+            &mut shader,
+            &viewport,
+            &mut serial_zbuffer,
+            &mut serial_image,
+        );
+
+        let mut zbuffer = vec![f64::MIN; width * height];
+        let mut image = Image::new(width, height, Format::RGB);
+        let stats = draw(&faces, &mut shader, &viewport, &mut zbuffer, &mut image);
+
+        assert_eq!(stats, serial_stats);
+        assert!(zbuffer == serial_zbuffer, "the zbuffers differ");
+        assert!(
+            image.encode_tga(false).unwrap() == serial_image.encode_tga(false).unwrap(),
+            "the images differ"
+        );
+    }
+}
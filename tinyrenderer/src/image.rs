@@ -0,0 +1,448 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+// tga image types this reader understands
+const UNCOMPRESSED_TRUE_COLOR: u8 = 2;
+const UNCOMPRESSED_GRAYSCALE: u8 = 3;
+const RLE_TRUE_COLOR: u8 = 10;
+const RLE_GRAYSCALE: u8 = 11;
+
+// image descriptor bits that say where the first pixel in the file goes
+const RIGHT_TO_LEFT: u8 = 0x10;
+const TOP_TO_BOTTOM: u8 = 0x20;
+
+const HEADER_SIZE: usize = 18;
+
+// the value of each format is the number of bytes it takes to store a pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Grayscale = 1,
+    RGB = 3,
+    RGBA = 4,
+}
+
+impl Format {
+    pub fn bytes_per_pixel(self) -> usize {
+        self as usize
+    }
+
+    fn from_depth(depth: u8) -> Option<Format> {
+        match depth {
+            8 => Some(Format::Grayscale),
+            24 => Some(Format::RGB),
+            32 => Some(Format::RGBA),
+            _ => None,
+        }
+    }
+}
+
+// colors are stored in the same blue, green, red, alpha order tga files use
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Color {
+    pub bgra: [u8; 4],
+}
+
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { bgra: [b, g, r, a] }
+    }
+
+    pub fn gray(value: u8) -> Color {
+        Color::new(value, value, value, 255)
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    // the file ended before the header or all of the pixels could be read
+    Truncated,
+    // the image type is one this reader doesn't handle, ie color mapped images
+    UnsupportedType(u8),
+    // the pixel depth doesn't match the image type or isn't 8, 24 or 32 bits
+    UnsupportedDepth(u8),
+    // the width and height of an image too large for the 16 bits a tga file stores them in
+    TooLarge(usize, usize),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageError::Io(ref err) => write!(f, "{}", err),
+            ImageError::Truncated => write!(f, "tga file is truncated"),
+            ImageError::UnsupportedType(kind) => write!(f, "unsupported tga image type {}", kind),
+            ImageError::UnsupportedDepth(depth) => {
+                write!(f, "unsupported tga pixel depth of {} bits", depth)
+            }
+            ImageError::TooLarge(width, height) => write!(
+                f,
+                "a {}x{} image is too large for a tga file, which holds at most {} pixels each way",
+                width,
+                height,
+                u16::MAX
+            ),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ImageError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(err: io::Error) -> ImageError {
+        ImageError::Io(err)
+    }
+}
+
+// an image with its origin at the bottom left, so y grows upwards like the rest of the renderer
+#[derive(Clone, Debug)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    format: Format,
+    data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, format: Format) -> Image {
+        Image {
+            width,
+            height,
+            format,
+            data: vec![0; width * height * format.bytes_per_pixel()],
+        }
+    }
+
+    pub fn read_tga<P: AsRef<Path>>(filename: P) -> Result<Image, ImageError> {
+        let mut bytes = Vec::new();
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        Image::decode_tga(&bytes)
+    }
+
+    pub fn decode_tga(bytes: &[u8]) -> Result<Image, ImageError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(ImageError::Truncated);
+        }
+        let id_length = bytes[0] as usize;
+        let color_map_type = bytes[1];
+        let image_type = bytes[2];
+        let color_map_length = u16::from_le_bytes([bytes[5], bytes[6]]) as usize;
+        let color_map_depth = bytes[7] as usize;
+        let width = u16::from_le_bytes([bytes[12], bytes[13]]) as usize;
+        let height = u16::from_le_bytes([bytes[14], bytes[15]]) as usize;
+        let depth = bytes[16];
+        let descriptor = bytes[17];
+
+        let format = Format::from_depth(depth).ok_or(ImageError::UnsupportedDepth(depth))?;
+        let rle = match image_type {
+            UNCOMPRESSED_TRUE_COLOR | UNCOMPRESSED_GRAYSCALE => false,
+            RLE_TRUE_COLOR | RLE_GRAYSCALE => true,
+            _ => return Err(ImageError::UnsupportedType(image_type)),
+        };
+        let grayscale = image_type == UNCOMPRESSED_GRAYSCALE || image_type == RLE_GRAYSCALE;
+        if grayscale != (format == Format::Grayscale) {
+            return Err(ImageError::UnsupportedDepth(depth));
+        }
+
+        // skip the image id and any color map, true color images don't use it
+        let mut offset = HEADER_SIZE + id_length;
+        if color_map_type != 0 {
+            offset += color_map_length * color_map_depth.div_ceil(8);
+        }
+        if offset > bytes.len() {
+            return Err(ImageError::Truncated);
+        }
+
+        let mut image = Image::new(width, height, format);
+        if rle {
+            image.decode_rle(&bytes[offset..])?;
+        } else {
+            let size = image.data.len();
+            if bytes.len() - offset < size {
+                return Err(ImageError::Truncated);
+            }
+            image.data.copy_from_slice(&bytes[offset..offset + size]);
+        }
+
+        // pixels are kept bottom to top and left to right, flip anything stored the other way
+        if descriptor & TOP_TO_BOTTOM != 0 {
+            image.flip_vertically();
+        }
+        if descriptor & RIGHT_TO_LEFT != 0 {
+            image.flip_horizontally();
+        }
+        Ok(image)
+    }
+
+    fn decode_rle(&mut self, bytes: &[u8]) -> Result<(), ImageError> {
+        let bytespp = self.format.bytes_per_pixel();
+        let mut input = 0;
+        let mut output = 0;
+        while output < self.data.len() {
+            let header = *bytes.get(input).ok_or(ImageError::Truncated)?;
+            input += 1;
+            // the low 7 bits hold the number of pixels in the packet minus one
+            let count = (header & 0x7f) as usize + 1;
+            let size = count * bytespp;
+            if output + size > self.data.len() {
+                return Err(ImageError::Truncated);
+            }
+            if header & 0x80 == 0 {
+                // raw packet, count pixels follow as is
+                let raw = bytes
+                    .get(input..input + size)
+                    .ok_or(ImageError::Truncated)?;
+                self.data[output..output + size].copy_from_slice(raw);
+                input += size;
+            } else {
+                // run length packet, a single pixel repeated count times
+                let pixel = bytes
+                    .get(input..input + bytespp)
+                    .ok_or(ImageError::Truncated)?;
+                for chunk in self.data[output..output + size].chunks_mut(bytespp) {
+                    chunk.copy_from_slice(pixel);
+                }
+                input += bytespp;
+            }
+            output += size;
+        }
+        Ok(())
+    }
+
+    pub fn write_tga<P: AsRef<Path>>(&self, filename: P, rle: bool) -> Result<(), ImageError> {
+        let bytes = self.encode_tga(rle)?;
+        let mut file = io::BufWriter::new(File::create(filename)?);
+        file.write_all(&bytes)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn encode_tga(&self, rle: bool) -> Result<Vec<u8>, ImageError> {
+        if self.width > u16::MAX as usize || self.height > u16::MAX as usize {
+            return Err(ImageError::TooLarge(self.width, self.height));
+        }
+        let grayscale = self.format == Format::Grayscale;
+        let image_type = match (grayscale, rle) {
+            (true, false) => UNCOMPRESSED_GRAYSCALE,
+            (true, true) => RLE_GRAYSCALE,
+            (false, false) => UNCOMPRESSED_TRUE_COLOR,
+            (false, true) => RLE_TRUE_COLOR,
+        };
+
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[2] = image_type;
+        bytes[12..14].copy_from_slice(&(self.width as u16).to_le_bytes());
+        bytes[14..16].copy_from_slice(&(self.height as u16).to_le_bytes());
+        bytes[16] = (self.format.bytes_per_pixel() * 8) as u8;
+        // rows are already stored bottom to top, which is the tga default, only note the alpha
+        // bits in the descriptor
+        bytes[17] = if self.format == Format::RGBA { 8 } else { 0 };
+
+        if rle {
+            self.encode_rle(&mut bytes);
+        } else {
+            bytes.extend_from_slice(&self.data);
+        }
+        Ok(bytes)
+    }
+
+    fn encode_rle(&self, bytes: &mut Vec<u8>) {
+        let bytespp = self.format.bytes_per_pixel();
+        // packets can't cross a scanline
+        for row in self.data.chunks(self.width.max(1) * bytespp) {
+            let pixels: Vec<&[u8]> = row.chunks(bytespp).collect();
+            let mut i = 0;
+            while i < pixels.len() {
+                // count how many of the following pixels are identical
+                let mut run = 1;
+                while run < 128 && i + run < pixels.len() && pixels[i + run] == pixels[i] {
+                    run += 1;
+                }
+                if run > 1 {
+                    bytes.push(0x80 | (run - 1) as u8);
+                    bytes.extend_from_slice(pixels[i]);
+                    i += run;
+                    continue;
+                }
+
+                // otherwise gather pixels until the next run starts
+                let start = i;
+                while i < pixels.len()
+                    && i - start < 128
+                    && (i + 1 >= pixels.len() || pixels[i] != pixels[i + 1])
+                {
+                    i += 1;
+                }
+                bytes.push((i - start - 1) as u8);
+                for pixel in &pixels[start..i] {
+                    bytes.extend_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    // returns black for any pixel outside the image
+    pub fn get(&self, x: i32, y: i32) -> Color {
+        let index = match self.index(x, y) {
+            Some(index) => index,
+            None => return Color::default(),
+        };
+        let pixel = &self.data[index..index + self.format.bytes_per_pixel()];
+        match self.format {
+            Format::Grayscale => Color::gray(pixel[0]),
+            Format::RGB => Color {
+                bgra: [pixel[0], pixel[1], pixel[2], 255],
+            },
+            Format::RGBA => Color {
+                bgra: [pixel[0], pixel[1], pixel[2], pixel[3]],
+            },
+        }
+    }
+
+    // returns false when the pixel is outside the image and nothing was written
+    pub fn set(&mut self, x: i32, y: i32, color: Color) -> bool {
+        let index = match self.index(x, y) {
+            Some(index) => index,
+            None => return false,
+        };
+        let bytespp = self.format.bytes_per_pixel();
+        self.data[index..index + bytespp].copy_from_slice(&color.bgra[..bytespp]);
+        true
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some((x as usize + y as usize * self.width) * self.format.bytes_per_pixel())
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_format(&self) -> Format {
+        self.format
+    }
+
+    pub fn flip_vertically(&mut self) {
+        let row = self.width * self.format.bytes_per_pixel();
+        for y in 0..self.height / 2 {
+            let (top, bottom) = self.data.split_at_mut((self.height - 1 - y) * row);
+            top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+        }
+    }
+
+    pub fn flip_horizontally(&mut self) {
+        let bytespp = self.format.bytes_per_pixel();
+        let row = self.width * bytespp;
+        for line in self.data.chunks_mut(row.max(1)) {
+            for x in 0..self.width / 2 {
+                let mirror = self.width - 1 - x;
+                for i in 0..bytespp {
+                    line.swap(x * bytespp + i, mirror * bytespp + i);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rows wider than a packet can hold, with long and short runs and stretches of pixels that
+    // all differ, some of them too long for a single packet too
+    fn pattern(format: Format) -> Image {
+        let (width, height) = (400, 4);
+        let mut image = Image::new(width, height, format);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let value = match x {
+                    0..=199 => y * 50,
+                    200..=209 => x / 2,
+                    _ => x * 7 + y,
+                };
+                let value = value as u8;
+                image.set(
+                    x,
+                    y,
+                    Color::new(value, value ^ 0x55, 255 - value, value / 2),
+                );
+            }
+        }
+        image
+    }
+
+    fn assert_same(image: &Image, expected: &Image) {
+        assert_eq!(image.get_format(), expected.get_format());
+        assert_eq!(image.get_width(), expected.get_width());
+        assert_eq!(image.get_height(), expected.get_height());
+        assert!(image.data == expected.data, "the pixels differ");
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        for &format in &[Format::Grayscale, Format::RGB, Format::RGBA] {
+            let image = pattern(format);
+            let bytes = image.encode_tga(true).unwrap();
+            assert!(bytes.len() < image.encode_tga(false).unwrap().len());
+            assert_same(&Image::decode_tga(&bytes).unwrap(), &image);
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        for &rle in &[false, true] {
+            let mut bytes = pattern(Format::RGB).encode_tga(rle).unwrap();
+            bytes.pop();
+            assert!(matches!(
+                Image::decode_tga(&bytes),
+                Err(ImageError::Truncated)
+            ));
+            assert!(matches!(
+                Image::decode_tga(&bytes[..HEADER_SIZE - 1]),
+                Err(ImageError::Truncated)
+            ));
+        }
+    }
+
+    #[test]
+    fn top_to_bottom_files_are_flipped() {
+        for &rle in &[false, true] {
+            let image = pattern(Format::RGB);
+            let mut flipped = image.clone();
+            flipped.flip_vertically();
+            let mut bytes = flipped.encode_tga(rle).unwrap();
+            bytes[17] |= TOP_TO_BOTTOM;
+            assert_same(&Image::decode_tga(&bytes).unwrap(), &image);
+        }
+    }
+
+    #[test]
+    fn images_too_large_for_tga_are_errors() {
+        for &(width, height) in &[(65536, 1), (1, 65536)] {
+            assert!(matches!(
+                Image::new(width, height, Format::Grayscale).encode_tga(false),
+                Err(ImageError::TooLarge(w, h)) if (w, h) == (width, height)
+            ));
+        }
+        assert!(Image::new(65535, 1, Format::Grayscale)
+            .encode_tga(true)
+            .is_ok());
+    }
+}
@@ -0,0 +1,19 @@
+extern crate cgmath;
+extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+
+pub mod camera;
+pub mod gl;
+pub mod image;
+pub mod light;
+pub mod obj;
+pub mod scene;
+pub mod shader;
+pub mod shadow;
+pub mod ssao;
+pub mod supersample;
+pub mod texture;
+pub mod transform;
@@ -0,0 +1,160 @@
+use cgmath;
+use cgmath::InnerSpace;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    // infinitely far away, direction points towards the light
+    Directional {
+        direction: cgmath::Vector3<f64>,
+    },
+    // shines in every direction from position, see attenuate for the falloff
+    Point {
+        position: cgmath::Vector3<f64>,
+        attenuation: cgmath::Vector3<f64>,
+    },
+    // a point light limited to a cone around direction, which points away from the light. full
+    // intensity inside inner_angle fading out to nothing at outer_angle, both in degrees from
+    // the axis of the cone
+    Spot {
+        position: cgmath::Vector3<f64>,
+        direction: cgmath::Vector3<f64>,
+        inner_angle: f64,
+        outer_angle: f64,
+        attenuation: cgmath::Vector3<f64>,
+    },
+}
+
+// positions and directions are in world coords, color is rgb in the range [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: cgmath::Vector3<f64>,
+    pub intensity: f64,
+}
+
+impl Light {
+    pub fn directional(direction: cgmath::Vector3<f64>) -> Light {
+        Light::new(LightKind::Directional {
+            direction: direction.normalize(),
+        })
+    }
+
+    pub fn point(position: cgmath::Vector3<f64>, attenuation: cgmath::Vector3<f64>) -> Light {
+        Light::new(LightKind::Point {
+            position,
+            attenuation,
+        })
+    }
+
+    pub fn spot(
+        position: cgmath::Vector3<f64>,
+        direction: cgmath::Vector3<f64>,
+        inner_angle: f64,
+        outer_angle: f64,
+        attenuation: cgmath::Vector3<f64>,
+    ) -> Light {
+        Light::new(LightKind::Spot {
+            position,
+            direction: direction.normalize(),
+            inner_angle,
+            outer_angle,
+            attenuation,
+        })
+    }
+
+    // a white light at full intensity
+    pub fn new(kind: LightKind) -> Light {
+        Light {
+            kind,
+            color: cgmath::vec3(1., 1., 1.),
+            intensity: 1.,
+        }
+    }
+
+    // returns the direction from point towards the light and the rgb light reaching point
+    pub fn illuminate(
+        &self,
+        point: cgmath::Vector3<f64>,
+    ) -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>) {
+        let radiance = self.color * self.intensity;
+        match self.kind {
+            LightKind::Directional { direction } => (direction, radiance),
+            LightKind::Point {
+                position,
+                attenuation,
+            } => {
+                let to_light = position - point;
+                let distance = to_light.magnitude();
+                (
+                    to_light / distance,
+                    radiance * attenuate(attenuation, distance),
+                )
+            }
+            LightKind::Spot {
+                position,
+                direction,
+                inner_angle,
+                outer_angle,
+                attenuation,
+            } => {
+                let to_light = position - point;
+                let distance = to_light.magnitude();
+                let to_light = to_light / distance;
+                // compare cosines instead of angles, cos shrinks as the angle grows
+                let cos_angle = -to_light.dot(direction);
+                let cos_inner = inner_angle.to_radians().cos();
+                let cos_outer = outer_angle.to_radians().cos();
+                let cone = if cos_angle >= cos_inner {
+                    1.
+                } else if cos_angle <= cos_outer {
+                    0.
+                } else {
+                    (cos_angle - cos_outer) / (cos_inner - cos_outer)
+                };
+                (to_light, radiance * attenuate(attenuation, distance) * cone)
+            }
+        }
+    }
+}
+
+// attenuation holds the constant, linear and quadratic terms of the falloff with distance
+fn attenuate(attenuation: cgmath::Vector3<f64>, distance: f64) -> f64 {
+    1. / (attenuation.x + attenuation.y * distance + attenuation.z * distance * distance)
+}
+
+// adds up the light every light in lights sends along the normal at point, as an rgb multiplier
+// for the surface color
+pub fn diffuse(
+    lights: &[Light],
+    point: cgmath::Vector3<f64>,
+    normal: cgmath::Vector3<f64>,
+) -> cgmath::Vector3<f64> {
+    lights
+        .iter()
+        .fold(cgmath::vec3(0., 0., 0.), |total, light| {
+            let (direction, radiance) = light.illuminate(point);
+            total + radiance * normal.dot(direction).max(0.)
+        })
+}
+
+// adds up the blinn phong highlights every light puts on the surface at point for a viewer in
+// the direction of view, a bigger exponent makes for smaller and sharper highlights
+pub fn specular(
+    lights: &[Light],
+    point: cgmath::Vector3<f64>,
+    normal: cgmath::Vector3<f64>,
+    view: cgmath::Vector3<f64>,
+    exponent: f64,
+) -> cgmath::Vector3<f64> {
+    lights
+        .iter()
+        .fold(cgmath::vec3(0., 0., 0.), |total, light| {
+            let (direction, radiance) = light.illuminate(point);
+            // surfaces facing away from the light can't reflect it
+            if normal.dot(direction) <= 0. {
+                return total;
+            }
+            let halfway = (direction + view).normalize();
+            total + radiance * normal.dot(halfway).max(0.).powf(exponent)
+        })
+}
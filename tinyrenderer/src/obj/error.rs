@@ -0,0 +1,86 @@
+use image::ImageError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// the material problems, MissingMaterial, Map and Material, don't stop a model from loading and
+// only show up in its warnings
+#[derive(Debug)]
+pub enum ObjErrorKind {
+    Io(io::Error),
+    // a statement is missing one of its values, holds the statement keyword
+    MissingValue(String),
+    // holds the token that couldn't be parsed as a number
+    InvalidNumber(String),
+    // holds the face token that points at an element that doesn't exist
+    InvalidIndex(String),
+    // holds the face vertex that doesn't match the v, v/vt, v//vn or v/vt/vn format used by the
+    // rest of the face
+    InvalidFace(String),
+    // holds the name passed to usemtl that no mtllib defined, or the mtl statement that came
+    // before any newmtl
+    MissingMaterial(String),
+    // holds the path of a texture map named by a material that couldn't be read
+    Map(String, ImageError),
+    // loading one of the model's mtl libraries failed
+    Material(Box<ObjError>),
+}
+
+// where in which file loading a model went wrong, line is 0 when the file couldn't be opened
+#[derive(Debug)]
+pub struct ObjError {
+    pub filename: String,
+    pub line: usize,
+    pub kind: ObjErrorKind,
+}
+
+impl ObjError {
+    pub fn new(filename: &Path, line: usize, kind: ObjErrorKind) -> ObjError {
+        ObjError {
+            filename: filename.display().to_string(),
+            line,
+            kind,
+        }
+    }
+
+    // the piece of the line that caused the error, if there is one
+    pub fn token(&self) -> Option<&str> {
+        match self.kind {
+            ObjErrorKind::Io(_) | ObjErrorKind::Map(..) => None,
+            ObjErrorKind::MissingValue(ref token)
+            | ObjErrorKind::InvalidNumber(ref token)
+            | ObjErrorKind::InvalidIndex(ref token)
+            | ObjErrorKind::InvalidFace(ref token)
+            | ObjErrorKind::MissingMaterial(ref token) => Some(token),
+            ObjErrorKind::Material(ref err) => err.token(),
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: ", self.filename, self.line)?;
+        match self.kind {
+            ObjErrorKind::Io(ref err) => write!(f, "{}", err),
+            ObjErrorKind::MissingValue(ref token) => write!(f, "'{}' is missing a value", token),
+            ObjErrorKind::InvalidNumber(ref token) => write!(f, "invalid number '{}'", token),
+            ObjErrorKind::InvalidIndex(ref token) => write!(f, "invalid index '{}'", token),
+            ObjErrorKind::InvalidFace(ref token) => write!(f, "invalid face vertex '{}'", token),
+            ObjErrorKind::MissingMaterial(ref token) => write!(f, "no material for '{}'", token),
+            ObjErrorKind::Map(ref path, ref err) => write!(f, "{}: {}", path, err),
+            ObjErrorKind::Material(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.kind {
+            ObjErrorKind::Io(ref err) => Some(err),
+            ObjErrorKind::Map(_, ref err) => Some(err),
+            ObjErrorKind::Material(ref err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
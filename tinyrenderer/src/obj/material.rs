@@ -0,0 +1,206 @@
+extern crate cgmath;
+
+use super::error::{ObjError, ObjErrorKind};
+use super::model::{parse_number, parse_vector};
+use image::Image;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use texture::Texture;
+
+// the coords the normals in a normal map are in
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NormalSpace {
+    // the model's own coords, the map only fits the model it was made for
+    Object,
+    // relative to the surface, along the model's tangent, bitangent and vertex normal
+    Tangent,
+}
+
+// a material from an mtl library, colors are rgb in the range [0, 1]
+pub struct Material {
+    pub name: String,
+    // Kd
+    pub diffuse_color: cgmath::Vector3<f64>,
+    // Ks, None when the library doesn't give one
+    pub specular_color: Option<cgmath::Vector3<f64>>,
+    // Ns, the specular exponent
+    pub shininess: f64,
+    // d, 1 is fully opaque
+    pub dissolve: f64,
+    // map_Kd
+    pub diffuse_map: Option<Texture>,
+    // map_Bump, bump or norm
+    pub normal_map: Option<Texture>,
+    // norm statements and maps named like head_nm_tangent.tga are taken to be in tangent space
+    pub normal_space: NormalSpace,
+    // map_Ks, the color of the highlights at each pixel
+    pub specular_map: Option<Texture>,
+    // map_Ns, the specular exponent at each pixel
+    pub shininess_map: Option<Texture>,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            diffuse_color: cgmath::vec3(1., 1., 1.),
+            specular_color: None,
+            shininess: 1.,
+            dissolve: 1.,
+            diffuse_map: None,
+            normal_map: None,
+            normal_space: NormalSpace::Object,
+            specular_map: None,
+            shininess_map: None,
+        }
+    }
+
+    // reads every material in an mtl file, texture maps are loaded relative to the file. a map
+    // that can't be read, say because it isn't a tga, leaves the material without it and is
+    // added to warnings
+    pub fn load_library<P: AsRef<Path>>(
+        filename: P,
+        warnings: &mut Vec<ObjError>,
+    ) -> Result<Vec<Material>, ObjError> {
+        let filename = filename.as_ref();
+        let directory = filename.parent().unwrap_or_else(|| Path::new(""));
+        let file = File::open(filename)
+            .map_err(|err| ObjError::new(filename, 0, ObjErrorKind::Io(err)))?;
+        let reader = io::BufReader::new(file);
+
+        let mut materials: Vec<Material> = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line =
+                line.map_err(|err| ObjError::new(filename, number + 1, ObjErrorKind::Io(err)))?;
+            let mut problems = Vec::new();
+            parse_line(&line, directory, &mut materials, &mut problems)
+                .map_err(|kind| ObjError::new(filename, number + 1, kind))?;
+            warnings.extend(
+                problems
+                    .into_iter()
+                    .map(|kind| ObjError::new(filename, number + 1, kind)),
+            );
+        }
+        Ok(materials)
+    }
+}
+
+fn parse_line(
+    line: &str,
+    directory: &Path,
+    materials: &mut Vec<Material>,
+    warnings: &mut Vec<ObjErrorKind>,
+) -> Result<(), ObjErrorKind> {
+    let split: Vec<&str> = line.split_whitespace().collect();
+    if split.is_empty() {
+        return Ok(());
+    }
+    if split[0] == "newmtl" {
+        let name = split
+            .get(1)
+            .ok_or_else(|| ObjErrorKind::MissingValue(split[0].to_string()))?;
+        materials.push(Material::new(name));
+        return Ok(());
+    }
+    if split[0].starts_with('#') {
+        return Ok(());
+    }
+
+    // every other statement belongs to the last newmtl
+    let material = materials
+        .last_mut()
+        .ok_or_else(|| ObjErrorKind::MissingMaterial(split[0].to_string()))?;
+    match split[0] {
+        "Kd" => material.diffuse_color = parse_vector(&split, 3)?,
+        "Ks" => material.specular_color = Some(parse_vector(&split, 3)?),
+        "Ns" => material.shininess = parse_value(&split)?,
+        "d" => material.dissolve = parse_value(&split)?,
+        // some exporters write transparency instead of dissolve
+        "Tr" => material.dissolve = 1. - parse_value(&split)?,
+        "map_Kd" => material.diffuse_map = load_map(&split, directory, warnings)?,
+        "map_Bump" | "map_bump" | "bump" | "norm" => {
+            material.normal_map = load_map(&split, directory, warnings)?;
+            material.normal_space =
+                if split[0] == "norm" || split[split.len() - 1].contains("tangent") {
+                    NormalSpace::Tangent
+                } else {
+                    NormalSpace::Object
+                };
+        }
+        "map_Ks" => material.specular_map = load_map(&split, directory, warnings)?,
+        "map_Ns" => material.shininess_map = load_map(&split, directory, warnings)?,
+        // ambient colors, illumination models etc. aren't used by the renderer
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_value(split: &[&str]) -> Result<f64, ObjErrorKind> {
+    let token = split
+        .get(1)
+        .ok_or_else(|| ObjErrorKind::MissingValue(split[0].to_string()))?;
+    parse_number(token)
+}
+
+// map statements can carry options like "-bm 1.0" before the file name, so the file is always
+// taken from the end of the line. returns None and adds a warning when the file can't be read
+fn load_map(
+    split: &[&str],
+    directory: &Path,
+    warnings: &mut Vec<ObjErrorKind>,
+) -> Result<Option<Texture>, ObjErrorKind> {
+    if split.len() < 2 {
+        return Err(ObjErrorKind::MissingValue(split[0].to_string()));
+    }
+    let path = directory.join(split[split.len() - 1]);
+    match Image::read_tga(&path) {
+        Ok(image) => Ok(Some(Texture::new(image))),
+        Err(err) => {
+            warnings.push(ObjErrorKind::Map(path.display().to_string(), err));
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Format;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn specular_maps() {
+        let directory = env::temp_dir().join(format!("material-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        Image::new(2, 2, Format::RGB)
+            .write_tga(directory.join("map.tga"), false)
+            .unwrap();
+        let library = directory.join("test.mtl");
+        fs::write(
+            &library,
+            "newmtl colored\nKs 0.5 0.5 0.5\nmap_Ks map.tga\n\
+             newmtl exponent\nmap_Ns map.tga\nmap_Ks missing.tga\n",
+        )
+        .unwrap();
+
+        let mut warnings = Vec::new();
+        let materials = Material::load_library(&library, &mut warnings).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            materials[0].specular_color,
+            Some(cgmath::vec3(0.5, 0.5, 0.5))
+        );
+        assert!(materials[0].specular_map.is_some());
+        assert!(materials[0].shininess_map.is_none());
+        // without a Ks line the renderer picks the color
+        assert_eq!(materials[1].specular_color, None);
+        assert!(materials[1].shininess_map.is_some());
+        assert!(materials[1].specular_map.is_none());
+        assert_eq!(warnings.len(), 1);
+        assert!(matches!(warnings[0].kind, ObjErrorKind::Map(..)));
+    }
+}
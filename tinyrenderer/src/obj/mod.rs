@@ -0,0 +1,7 @@
+pub use self::error::{ObjError, ObjErrorKind};
+pub use self::material::{Material, NormalSpace};
+pub use self::model::{Face, Model};
+mod error;
+mod material;
+mod model;
+mod triangulate;
@@ -0,0 +1,487 @@
+extern crate cgmath;
+
+use super::error::{ObjError, ObjErrorKind};
+use super::material::Material;
+use super::triangulate::triangulate;
+use cgmath::InnerSpace;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+pub struct Face {
+    // vertices that make up a face, always a triangle
+    pub vertices: Vec<usize>,
+    // index to texture coords for the face
+    pub texture_indices: Vec<usize>,
+    // index to vertex normals
+    pub normals_indices: Vec<usize>,
+    // index into the model's materials, set by the last usemtl before the face
+    pub material: Option<usize>,
+}
+
+//#[derive(Copy, Clone)]
+pub struct Model {
+    // vector containing all vertices in an object
+    pub vertices: Vec<cgmath::Vector3<f64>>,
+    // vector containing all vertex coordinates
+    pub texture_coords: Vec<cgmath::Vector3<f64>>,
+    // vector containing all vertex normals
+    pub vertex_normals: Vec<cgmath::Vector3<f64>>,
+    // the directions u and v grow in along the surface at each texture coord, indexed like
+    // texture_coords. they are what tangent space normal maps are relative to
+    pub tangents: Vec<cgmath::Vector3<f64>>,
+    pub bitangents: Vec<cgmath::Vector3<f64>>,
+    // vector containing each face struct
+    pub faces: Vec<Face>,
+    // every material from the mtl libraries the model uses
+    pub materials: Vec<Material>,
+    // problems with the materials that didn't stop the model from loading. libraries that
+    // couldn't be read and maps that couldn't be loaded are left out, and faces that use a
+    // material nobody defined get no material
+    pub warnings: Vec<ObjError>,
+}
+
+impl Model {
+    pub fn load<P: AsRef<Path>>(filename: P) -> Result<Model, ObjError> {
+        let filename = filename.as_ref();
+        let directory = filename.parent().unwrap_or_else(|| Path::new(""));
+        let file = File::open(filename)
+            .map_err(|err| ObjError::new(filename, 0, ObjErrorKind::Io(err)))?;
+        let reader = io::BufReader::new(file);
+
+        let mut model = Model {
+            vertices: Vec::new(),
+            texture_coords: Vec::new(),
+            vertex_normals: Vec::new(),
+            tangents: Vec::new(),
+            bitangents: Vec::new(),
+            faces: Vec::new(),
+            materials: Vec::new(),
+            warnings: Vec::new(),
+        };
+        // the material used by any faces that follow
+        let mut material = None;
+
+        for (number, line) in reader.lines().enumerate() {
+            let line =
+                line.map_err(|err| ObjError::new(filename, number + 1, ObjErrorKind::Io(err)))?;
+            let mut warnings = Vec::new();
+            model
+                .parse_line(&line, directory, &mut material, &mut warnings)
+                .map_err(|kind| ObjError::new(filename, number + 1, kind))?;
+            model.warnings.extend(
+                warnings
+                    .into_iter()
+                    .map(|kind| ObjError::new(filename, number + 1, kind)),
+            );
+        }
+        model.compute_tangents();
+        Ok(model)
+    }
+
+    // works out the tangents and bitangents from the positions and texture coords of the faces.
+    // every face that uses a texture coord adds its own to it so they come out smoothed across
+    // faces, while seams in the uv layout keep theirs apart
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![cgmath::vec3(0., 0., 0.); self.texture_coords.len()];
+        let mut bitangents = tangents.clone();
+        for face in &self.faces {
+            if face.texture_indices.len() < 3 {
+                continue;
+            }
+            let p0 = self.vertices[face.vertices[0]];
+            let uv0 = self.texture_coords[face.texture_indices[0]];
+            let edge1 = self.vertices[face.vertices[1]] - p0;
+            let edge2 = self.vertices[face.vertices[2]] - p0;
+            let delta1 = self.texture_coords[face.texture_indices[1]] - uv0;
+            let delta2 = self.texture_coords[face.texture_indices[2]] - uv0;
+
+            // solve edge = delta.x * tangent + delta.y * bitangent for both edges, a face
+            // without any area in uv space doesn't say anything about them
+            let determinant = delta1.x * delta2.y - delta2.x * delta1.y;
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+            let tangent = (edge1 * delta2.y - edge2 * delta1.y) / determinant;
+            let bitangent = (edge2 * delta1.x - edge1 * delta2.x) / determinant;
+            for &index in &face.texture_indices {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+        self.tangents = tangents.into_iter().map(normalize_or_zero).collect();
+        self.bitangents = bitangents.into_iter().map(normalize_or_zero).collect();
+    }
+
+    fn parse_line(
+        &mut self,
+        line: &str,
+        directory: &Path,
+        material: &mut Option<usize>,
+        warnings: &mut Vec<ObjErrorKind>,
+    ) -> Result<(), ObjErrorKind> {
+        // tokens can be separated by any amount of spaces or tabs
+        let split: Vec<&str> = line.split_whitespace().collect();
+        //ignore lines w/o data
+        if split.is_empty() {
+            return Ok(());
+        }
+        match split[0] {
+            // v denotes a regular vertex, x y z and an optional w. w is a weight for rational
+            // curves and surfaces, polygons don't use it so it's checked and then ignored
+            "v" => {
+                let vector = parse_vector(&split, 3)?;
+                if let Some(w) = split.get(4) {
+                    parse_number(w)?;
+                }
+                self.vertices.push(vector);
+            }
+            // u and optional v and w, missing values default to 0
+            "vt" => {
+                let vector = parse_vector(&split, 1)?;
+                self.texture_coords.push(vector);
+            }
+            "vn" => {
+                let normal = parse_vector(&split, 3)?;
+                self.vertex_normals.push(normal);
+            }
+            "f" => {
+                let mut vertices: Vec<usize> = Vec::new();
+                let mut texture_indices: Vec<usize> = Vec::new();
+                let mut normals_indices: Vec<usize> = Vec::new();
+
+                // ignore the first character "f"
+                for vertex in &split[1..] {
+                    // each vertex is one of v, v/vt, v//vn or v/vt/vn
+                    let group: Vec<&str> = vertex.split('/').collect();
+                    if group.len() > 3 {
+                        return Err(ObjErrorKind::InvalidFace(vertex.to_string()));
+                    }
+                    let has_texture = group.len() > 1 && !group[1].is_empty();
+                    let has_normal = group.len() > 2 && !group[2].is_empty();
+                    // every vertex of a face has to use the same format so the attribute indices
+                    // stay lined up with the vertices
+                    if !vertices.is_empty()
+                        && (has_texture == texture_indices.is_empty()
+                            || has_normal == normals_indices.is_empty())
+                    {
+                        return Err(ObjErrorKind::InvalidFace(vertex.to_string()));
+                    }
+
+                    vertices.push(parse_index(group[0], self.vertices.len())?);
+                    if has_texture {
+                        texture_indices.push(parse_index(group[1], self.texture_coords.len())?);
+                    }
+                    if has_normal {
+                        normals_indices.push(parse_index(group[2], self.vertex_normals.len())?);
+                    }
+                }
+                if vertices.len() < 3 {
+                    return Err(ObjErrorKind::MissingValue(split[0].to_string()));
+                }
+
+                // the rasterizer only draws triangles, so split up quads and larger polygons
+                // picking the same corners out of every index list
+                let points: Vec<cgmath::Vector3<f64>> =
+                    vertices.iter().map(|&v| self.vertices[v]).collect();
+                for triangle in triangulate(&points) {
+                    let pick = |indices: &[usize]| -> Vec<usize> {
+                        if indices.is_empty() {
+                            return Vec::new();
+                        }
+                        triangle.iter().map(|&corner| indices[corner]).collect()
+                    };
+                    let face = Face::new(
+                        pick(&vertices),
+                        pick(&texture_indices),
+                        pick(&normals_indices),
+                        *material,
+                    );
+                    self.faces.push(face);
+                }
+            }
+            // library files are relative to the obj file
+            "mtllib" => {
+                if split.len() < 2 {
+                    return Err(ObjErrorKind::MissingValue(split[0].to_string()));
+                }
+                for library in &split[1..] {
+                    let mut problems = Vec::new();
+                    match Material::load_library(directory.join(library), &mut problems) {
+                        Ok(materials) => self.materials.extend(materials),
+                        Err(err) => problems.push(err),
+                    }
+                    warnings.extend(
+                        problems
+                            .into_iter()
+                            .map(|err| ObjErrorKind::Material(Box::new(err))),
+                    );
+                }
+            }
+            "usemtl" => {
+                let name = split
+                    .get(1)
+                    .ok_or_else(|| ObjErrorKind::MissingValue(split[0].to_string()))?;
+                // later libraries override materials with the same name
+                *material = self.materials.iter().rposition(|m| &m.name == name);
+                if material.is_none() {
+                    warnings.push(ObjErrorKind::MissingMaterial(name.to_string()));
+                }
+            }
+            // anything else (comments, groups, smoothing...) isn't used by the renderer
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn num_vertices(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn num_faces(&self) -> usize {
+        self.faces.len()
+    }
+    pub fn get_vertex(&self, x: usize) -> &cgmath::Vector3<f64> {
+        &self.vertices[x]
+    }
+    pub fn get_texture_coord(&self, x: usize) -> &cgmath::Vector3<f64> {
+        &self.texture_coords[x]
+    }
+    pub fn get_vertex_normal(&self, x: usize) -> &cgmath::Vector3<f64> {
+        &self.vertex_normals[x]
+    }
+    pub fn get_tangent(&self, x: usize) -> &cgmath::Vector3<f64> {
+        &self.tangents[x]
+    }
+    pub fn get_bitangent(&self, x: usize) -> &cgmath::Vector3<f64> {
+        &self.bitangents[x]
+    }
+    pub fn get_material(&self, face: &Face) -> Option<&Material> {
+        face.material.map(|index| &self.materials[index])
+    }
+
+    // splits the faces into runs that share a material, in the order they appear in the file, so
+    // each run can be drawn with its own textures
+    pub fn groups(&self) -> Vec<(Option<&Material>, &[Face])> {
+        let mut groups = Vec::new();
+        let mut start = 0;
+        for i in 1..=self.faces.len() {
+            if i == self.faces.len() || self.faces[i].material != self.faces[start].material {
+                groups.push((self.get_material(&self.faces[start]), &self.faces[start..i]));
+                start = i;
+            }
+        }
+        groups
+    }
+}
+
+impl Face {
+    pub fn new(
+        vertices: Vec<usize>,
+        texture_indices: Vec<usize>,
+        normals_indices: Vec<usize>,
+        material: Option<usize>,
+    ) -> Face {
+        Face {
+            vertices,
+            texture_indices,
+            normals_indices,
+            material,
+        }
+    }
+}
+
+fn normalize_or_zero(vector: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+    let length = vector.magnitude();
+    if length > 0. {
+        vector / length
+    } else {
+        vector
+    }
+}
+
+// reads up to three values following the statement keyword, at least required of them have to
+// be there and the rest default to 0
+pub(super) fn parse_vector(
+    split: &[&str],
+    required: usize,
+) -> Result<cgmath::Vector3<f64>, ObjErrorKind> {
+    if split.len() < required + 1 {
+        return Err(ObjErrorKind::MissingValue(split[0].to_string()));
+    }
+    let mut vector = cgmath::vec3(0., 0., 0.);
+    for (i, token) in split[1..].iter().take(3).enumerate() {
+        vector[i] = parse_number(token)?;
+    }
+    Ok(vector)
+}
+
+pub(super) fn parse_number(token: &str) -> Result<f64, ObjErrorKind> {
+    token
+        .parse()
+        .map_err(|_| ObjErrorKind::InvalidNumber(token.to_string()))
+}
+
+// converts a 1 based obj index into a 0 based one, making sure the element it points at has
+// already been read. negative indices count backwards from the last element read so far
+fn parse_index(token: &str, count: usize) -> Result<usize, ObjErrorKind> {
+    let index: isize = token
+        .parse()
+        .map_err(|_| ObjErrorKind::InvalidIndex(token.to_string()))?;
+    let index = if index < 0 {
+        count as isize + index
+    } else {
+        index - 1
+    };
+    if index < 0 || index as usize >= count {
+        return Err(ObjErrorKind::InvalidIndex(token.to_string()));
+    }
+    Ok(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reads the lines of an obj file into an empty model the way load reads a file's
+    fn parse(source: &str) -> Result<Model, ObjErrorKind> {
+        let mut model = Model {
+            vertices: Vec::new(),
+            texture_coords: Vec::new(),
+            vertex_normals: Vec::new(),
+            tangents: Vec::new(),
+            bitangents: Vec::new(),
+            faces: Vec::new(),
+            materials: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut material = None;
+        let mut warnings = Vec::new();
+        for line in source.lines() {
+            model.parse_line(line, Path::new(""), &mut material, &mut warnings)?;
+        }
+        Ok(model)
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n";
+
+    #[test]
+    fn face_formats() {
+        let source = format!(
+            "{}f 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//1\nf 1/3/1 2/2/1 3/1/1\n",
+            TRIANGLE
+        );
+        let faces = parse(&source).unwrap().faces;
+        assert_eq!(faces.len(), 4);
+        for face in &faces {
+            assert_eq!(face.vertices, vec![0, 1, 2]);
+        }
+        assert!(faces[0].texture_indices.is_empty());
+        assert!(faces[0].normals_indices.is_empty());
+        assert_eq!(faces[1].texture_indices, vec![0, 1, 2]);
+        assert!(faces[1].normals_indices.is_empty());
+        assert!(faces[2].texture_indices.is_empty());
+        assert_eq!(faces[2].normals_indices, vec![0, 0, 0]);
+        assert_eq!(faces[3].texture_indices, vec![2, 1, 0]);
+        assert_eq!(faces[3].normals_indices, vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn polygons_are_split_into_triangles() {
+        let source = format!("{}v 1 1 0\nvt 1 1\nf 1/3 2/1 4/4 3/2\n", TRIANGLE);
+        let faces = parse(&source).unwrap().faces;
+        assert_eq!(faces.len(), 2);
+        assert_eq!(faces[0].vertices, vec![0, 1, 3]);
+        assert_eq!(faces[0].texture_indices, vec![2, 0, 3]);
+        assert_eq!(faces[1].vertices, vec![0, 3, 2]);
+        assert_eq!(faces[1].texture_indices, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn counts() {
+        let model = parse("").unwrap();
+        assert_eq!((model.num_vertices(), model.num_faces()), (0, 0));
+        let model = parse(&format!("{}f 1 2 3\n", TRIANGLE)).unwrap();
+        assert_eq!((model.num_vertices(), model.num_faces()), (3, 1));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element_read() {
+        let source = format!(
+            "{}f -3/-3/-1 -2/-2/-1 -1/-1/-1\nv 1 1 0\nf -1 -2 -4\n",
+            TRIANGLE
+        );
+        let faces = parse(&source).unwrap().faces;
+        assert_eq!(faces[0].vertices, vec![0, 1, 2]);
+        assert_eq!(faces[0].texture_indices, vec![0, 1, 2]);
+        assert_eq!(faces[0].normals_indices, vec![0, 0, 0]);
+        assert_eq!(faces[1].vertices, vec![3, 2, 0]);
+    }
+
+    #[test]
+    fn indices_have_to_point_at_elements_read_so_far() {
+        for face in &["f 0 1 2", "f 1 2 4", "f -4 1 2", "f 1/4 2/1 3/1", "f x 1 2"] {
+            let source = format!("{}{}\n", TRIANGLE, face);
+            assert!(
+                matches!(parse(&source), Err(ObjErrorKind::InvalidIndex(_))),
+                "{}",
+                face
+            );
+        }
+        assert!(matches!(
+            parse("v 0 0 0\nv 1 0 0\nf 1 2 3\nv 0 1 0\n"),
+            Err(ObjErrorKind::InvalidIndex(_))
+        ));
+    }
+
+    #[test]
+    fn faces_cannot_mix_formats() {
+        for face in &[
+            "f 1/1 2 3",
+            "f 1 2/2 3",
+            "f 1//1 2/2/1 3//1",
+            "f 1/1/1 2/2 3/3/1",
+            "f 1/1/1/1 2 3",
+        ] {
+            let source = format!("{}{}\n", TRIANGLE, face);
+            assert!(
+                matches!(parse(&source), Err(ObjErrorKind::InvalidFace(_))),
+                "{}",
+                face
+            );
+        }
+        let source = format!("{}f 1 2\n", TRIANGLE);
+        assert!(matches!(parse(&source), Err(ObjErrorKind::MissingValue(_))));
+    }
+
+    #[test]
+    fn tokens_can_be_separated_by_tabs_and_several_spaces() {
+        let model = parse("v\t1   2\t \t3\n  v 4 5 6  \nv 7 8 9\nf\t1  2\t\t3\n").unwrap();
+        assert_eq!(
+            model.vertices,
+            vec![
+                cgmath::vec3(1., 2., 3.),
+                cgmath::vec3(4., 5., 6.),
+                cgmath::vec3(7., 8., 9.)
+            ]
+        );
+        assert_eq!(model.faces[0].vertices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn optional_values() {
+        // w is a weight, not a divisor
+        let model = parse("v 2 4 6 2\nvt 0.5\nvt 0.5 0.25 0.125\n").unwrap();
+        assert_eq!(model.vertices, vec![cgmath::vec3(2., 4., 6.)]);
+        assert_eq!(
+            model.texture_coords,
+            vec![cgmath::vec3(0.5, 0., 0.), cgmath::vec3(0.5, 0.25, 0.125)]
+        );
+        assert!(matches!(
+            parse("v 1 2 3 x"),
+            Err(ObjErrorKind::InvalidNumber(_))
+        ));
+        assert!(matches!(parse("v 1 2"), Err(ObjErrorKind::MissingValue(_))));
+    }
+}
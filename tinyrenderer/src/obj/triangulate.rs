@@ -0,0 +1,213 @@
+extern crate cgmath;
+
+// splits a polygon into triangles, each triangle is returned as the positions of its corners in
+// the polygon so the matching texture and normal indices can be picked out as well. convex
+// polygons are split into a fan around the first corner, concave ones are ear clipped
+pub fn triangulate(points: &[cgmath::Vector3<f64>]) -> Vec<[usize; 3]> {
+    if points.len() <= 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    let points = project(points);
+    // the signed area says which way the polygon winds so convex corners can be told apart from
+    // reflex ones no matter how the file ordered them
+    let winding = signed_area(&points).signum();
+    let corners: Vec<usize> = (0..points.len()).collect();
+
+    if winding == 0. || is_convex(&points, winding) {
+        return fan(&corners);
+    }
+    ear_clip(&points, winding)
+}
+
+fn fan(corners: &[usize]) -> Vec<[usize; 3]> {
+    (1..corners.len() - 1)
+        .map(|i| [corners[0], corners[i], corners[i + 1]])
+        .collect()
+}
+
+fn ear_clip(points: &[cgmath::Vector2<f64>], winding: f64) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let prev = remaining[(i + count - 1) % count];
+            let current = remaining[i];
+            let next = remaining[(i + 1) % count];
+            if corner(points[prev], points[current], points[next]) * winding <= 0. {
+                return false;
+            }
+            // an ear can't have any of the other corners inside of it
+            !remaining
+                .iter()
+                .filter(|&&other| other != prev && other != current && other != next)
+                .any(|&other| {
+                    inside(
+                        points[other],
+                        [points[prev], points[current], points[next]],
+                        winding,
+                    )
+                })
+        });
+
+        match ear {
+            Some(i) => {
+                let prev = remaining[(i + count - 1) % count];
+                let next = remaining[(i + 1) % count];
+                triangles.push([prev, remaining[i], next]);
+                remaining.remove(i);
+            }
+            // self intersecting or otherwise broken polygon, fan whatever is left
+            None => {
+                triangles.extend(fan(&remaining));
+                return triangles;
+            }
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+// flattens the polygon onto the axis plane it faces the most, using newell's method to find its
+// normal
+fn project(points: &[cgmath::Vector3<f64>]) -> Vec<cgmath::Vector2<f64>> {
+    let mut normal = cgmath::vec3(0., 0., 0.);
+    for (i, current) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+        normal.x += (current.y - next.y) * (current.z + next.z);
+        normal.y += (current.z - next.z) * (current.x + next.x);
+        normal.z += (current.x - next.x) * (current.y + next.y);
+    }
+    let normal = cgmath::vec3(normal.x.abs(), normal.y.abs(), normal.z.abs());
+    let (x, y) = if normal.x >= normal.y && normal.x >= normal.z {
+        (1, 2)
+    } else if normal.y >= normal.z {
+        (2, 0)
+    } else {
+        (0, 1)
+    };
+    points
+        .iter()
+        .map(|point| cgmath::vec2(point[x], point[y]))
+        .collect()
+}
+
+fn signed_area(points: &[cgmath::Vector2<f64>]) -> f64 {
+    let mut area = 0.;
+    for (i, current) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+        area += current.x * next.y - next.x * current.y;
+    }
+    area / 2.
+}
+
+fn is_convex(points: &[cgmath::Vector2<f64>], winding: f64) -> bool {
+    let count = points.len();
+    (0..count).all(|i| {
+        let prev = points[(i + count - 1) % count];
+        let next = points[(i + 1) % count];
+        corner(prev, points[i], next) * winding >= 0.
+    })
+}
+
+// z of the cross product of the two edges meeting at current, positive when they turn counter
+// clockwise
+fn corner(
+    prev: cgmath::Vector2<f64>,
+    current: cgmath::Vector2<f64>,
+    next: cgmath::Vector2<f64>,
+) -> f64 {
+    let a = current - prev;
+    let b = next - current;
+    a.perp_dot(b)
+}
+
+fn inside(point: cgmath::Vector2<f64>, triangle: [cgmath::Vector2<f64>; 3], winding: f64) -> bool {
+    (0..3).all(|i| {
+        let edge = triangle[(i + 1) % 3] - triangle[i];
+        edge.perp_dot(point - triangle[i]) * winding >= 0.
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a polygon lying in the xz plane, so it has to be flattened onto it
+    fn polygon(corners: &[(f64, f64)]) -> Vec<cgmath::Vector3<f64>> {
+        corners
+            .iter()
+            .map(|&(x, z)| cgmath::vec3(x, 1., z))
+            .collect()
+    }
+
+    // splits the polygon both ways around and checks the triangles cover exactly the polygon's
+    // area, all winding the same way it does so none of them folds back over another
+    fn assert_covered(corners: &[(f64, f64)]) {
+        for &reversed in &[false, true] {
+            let mut corners = corners.to_vec();
+            if reversed {
+                corners.reverse();
+            }
+            let points = project(&polygon(&corners));
+            let triangles = triangulate(&polygon(&corners));
+            assert_eq!(triangles.len(), corners.len() - 2);
+
+            let area = signed_area(&points);
+            let mut total = 0.;
+            for triangle in &triangles {
+                let part = signed_area(&[
+                    points[triangle[0]],
+                    points[triangle[1]],
+                    points[triangle[2]],
+                ]);
+                assert!(part * area > 0., "{:?} winds the wrong way", triangle);
+                total += part;
+            }
+            assert!((total - area).abs() < 1e-9, "{} instead of {}", total, area);
+        }
+    }
+
+    #[test]
+    fn convex_polygons_are_fanned() {
+        let corners = [(0., 0.), (2., 0.), (3., 1.), (2., 2.), (0., 2.)];
+        assert_eq!(
+            triangulate(&polygon(&corners)),
+            vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]
+        );
+        assert_covered(&corners);
+    }
+
+    #[test]
+    fn concave_quad() {
+        // a dart, the corner at 2,1 points back into it so a fan around the first corner would
+        // cover the notch
+        assert_covered(&[(0., 0.), (2., 1.), (4., 0.), (2., 4.)]);
+        assert_covered(&[(2., 1.), (4., 0.), (2., 4.), (0., 0.)]);
+    }
+
+    #[test]
+    fn l_shape() {
+        assert_covered(&[(0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 2.), (0., 2.)]);
+        assert_covered(&[(1., 1.), (1., 2.), (0., 2.), (0., 0.), (2., 0.), (2., 1.)]);
+    }
+
+    #[test]
+    fn self_intersecting_polygons_still_use_every_corner() {
+        // ear clipping gets stuck partway through this one and fans the rest
+        let corners = [(0., 0.), (4., 0.), (1., 1.), (4., 2.), (0., 2.), (3., 1.)];
+        let triangles = triangulate(&polygon(&corners));
+        assert_eq!(triangles.len(), corners.len() - 2);
+        let mut used = [false; 6];
+        for triangle in &triangles {
+            assert!(triangle[0] != triangle[1] && triangle[1] != triangle[2]);
+            assert!(triangle[0] != triangle[2]);
+            for &corner in triangle {
+                used[corner] = true;
+            }
+        }
+        assert!(used.iter().all(|&used| used));
+    }
+}
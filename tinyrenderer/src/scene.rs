@@ -0,0 +1,676 @@
+use camera::{Camera, Projection};
+use cgmath;
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+use gl;
+use gl::{Msaa, SampleBuffer, Stats};
+use image::{Color, Format, Image, ImageError};
+use light::Light;
+use obj::{Model, NormalSpace, ObjError};
+use shader::PhongShader;
+use shadow::{ShadowMap, ShadowSettings};
+use ssao;
+use ssao::SsaoSettings;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use supersample;
+use supersample::{ResolveFilter, SupersampleSettings};
+use texture::{Filter, Sampler, Texture, Wrap};
+use toml;
+use transform;
+use transform::Transform;
+
+// the depth range the viewport maps clip coords to
+const DEPTH: f64 = 255.;
+
+// what render_frame drew
+pub struct Frame {
+    pub image: Image,
+    // a grayscale picture of the occlusion, see ssao::to_image. None when it wasn't worked out
+    pub occlusion: Option<Image>,
+    // what became of the faces drawn from the camera, the shadow maps aren't counted
+    pub stats: Stats,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    // the scene file couldn't be read
+    Io(io::Error),
+    // the scene file isn't valid toml or doesn't match the scene layout
+    Parse(toml::de::Error),
+    // one of the models couldn't be loaded
+    Model(ObjError),
+    // holds the path of a texture override that couldn't be loaded
+    Texture(String, ImageError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io(ref err) => write!(f, "{}", err),
+            SceneError::Parse(ref err) => write!(f, "invalid scene: {}", err),
+            SceneError::Model(ref err) => write!(f, "{}", err),
+            SceneError::Texture(ref path, ref err) => write!(f, "{}: {}", path, err),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SceneError::Io(ref err) => Some(err),
+            SceneError::Parse(ref err) => Some(err),
+            SceneError::Model(ref err) => Some(err),
+            SceneError::Texture(_, ref err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> SceneError {
+        SceneError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(err: toml::de::Error) -> SceneError {
+        SceneError::Parse(err)
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(err: ObjError) -> SceneError {
+        SceneError::Model(err)
+    }
+}
+
+// a model placed in the scene, the overrides win over whatever its materials say
+pub struct SceneModel {
+    pub model: Model,
+    // takes model coords to world coords
+    pub transform: cgmath::Matrix4<f64>,
+    pub diffuse_color: Option<cgmath::Vector3<f64>>,
+    pub diffuse_map: Option<Texture>,
+    pub normal_map: Option<Texture>,
+    // the space normal_map is in
+    pub normal_space: NormalSpace,
+    // the specular exponent of each pixel, like a material's map_Ns
+    pub specular_map: Option<Texture>,
+    // the color of the highlights, without it or a material to say otherwise a specular map
+    // gets white ones
+    pub specular_color: Option<cgmath::Vector3<f64>>,
+    // how uvs outside of [0, 1] wrap on this model's textures, say to tile a floor, the scene's
+    // sampler decides when None
+    pub wrap: Option<Wrap>,
+}
+
+impl SceneModel {
+    pub fn new(model: Model) -> SceneModel {
+        SceneModel {
+            model,
+            transform: cgmath::Matrix4::identity(),
+            diffuse_color: None,
+            diffuse_map: None,
+            normal_map: None,
+            normal_space: NormalSpace::Object,
+            specular_map: None,
+            specular_color: None,
+            wrap: None,
+        }
+    }
+}
+
+// everything needed to render a frame, cameras are named so one can be picked when rendering
+pub struct Scene {
+    pub models: Vec<SceneModel>,
+    pub cameras: Vec<(String, Camera)>,
+    pub lights: Vec<Light>,
+    // rgb light that reaches every surface, black by default
+    pub ambient: cgmath::Vector3<f64>,
+    // directional and spot lights cast shadows when set, off by default
+    pub shadows: Option<ShadowSettings>,
+    // darkens creases after rendering when set, off by default
+    pub ssao: Option<SsaoSettings>,
+    // how the models' textures are looked up, Sampler::default() by default
+    pub sampler: Sampler,
+    // smooths the edges of the models, off by default
+    pub msaa: Msaa,
+    // renders a larger image and shrinks it when set, off by default
+    pub supersample: Option<SupersampleSettings>,
+}
+
+// the layout of a scene file, see l5/src/assets/scene.toml for an example
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    model: Vec<ModelEntry>,
+    #[serde(default)]
+    camera: Vec<CameraEntry>,
+    #[serde(default)]
+    light: Vec<LightEntry>,
+    #[serde(default)]
+    ambient: [f64; 3],
+    shadows: Option<ShadowEntry>,
+    ssao: Option<SsaoEntry>,
+    sampler: Option<SamplerEntry>,
+    msaa: Option<MsaaEntry>,
+    supersample: Option<SupersampleEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelEntry {
+    file: String,
+    // a full model to world matrix, rows first. takes the place of translate, rotate and scale
+    matrix: Option<[[f64; 4]; 4]>,
+    #[serde(default)]
+    translate: [f64; 3],
+    // degrees around the x, y and z axes, applied in that order
+    #[serde(default)]
+    rotate: [f64; 3],
+    #[serde(default = "one")]
+    scale: [f64; 3],
+    diffuse_color: Option<[f64; 3]>,
+    diffuse: Option<String>,
+    normal: Option<String>,
+    // a tangent space normal map, takes the place of normal
+    tangent_normal: Option<String>,
+    specular: Option<String>,
+    specular_color: Option<[f64; 3]>,
+    wrap: Option<Wrap>,
+}
+
+// an empty [shadows] table turns shadows on with the default settings
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShadowEntry {
+    size: Option<usize>,
+    bias: Option<f64>,
+    pcf: Option<usize>,
+}
+
+// an empty [ssao] table turns ssao on with the default settings
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SsaoEntry {
+    directions: Option<usize>,
+    radius: Option<usize>,
+    max_distance: Option<f64>,
+    strength: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SamplerEntry {
+    filter: Option<Filter>,
+    wrap: Option<Wrap>,
+}
+
+// msaa = 1, 2, 4 or 8 samples per pixel
+#[derive(Deserialize)]
+#[serde(try_from = "usize")]
+struct MsaaEntry(Msaa);
+
+impl TryFrom<usize> for MsaaEntry {
+    type Error = String;
+
+    fn try_from(samples: usize) -> Result<MsaaEntry, String> {
+        Msaa::from_samples(samples)
+            .map(MsaaEntry)
+            .ok_or_else(|| format!("msaa takes 1, 2, 4 or 8 samples, not {}", samples))
+    }
+}
+
+// an empty [supersample] table turns supersampling on with the default settings
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SupersampleEntry {
+    factor: Option<usize>,
+    filter: Option<ResolveFilter>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraEntry {
+    name: Option<String>,
+    position: [f64; 3],
+    #[serde(default)]
+    target: [f64; 3],
+    #[serde(default = "up")]
+    up: [f64; 3],
+    #[serde(default = "fov")]
+    fov: f64,
+    near: Option<f64>,
+    far: Option<f64>,
+    #[serde(default)]
+    orthographic: bool,
+}
+
+// serde can't deny unknown fields next to a flattened enum
+#[derive(Deserialize)]
+struct LightEntry {
+    #[serde(flatten)]
+    kind: LightKindEntry,
+    #[serde(default = "one")]
+    color: [f64; 3],
+    #[serde(default = "intensity")]
+    intensity: f64,
+}
+
+// picked with type = "directional", "point" or "spot"
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum LightKindEntry {
+    Directional {
+        direction: [f64; 3],
+    },
+    Point {
+        position: [f64; 3],
+        #[serde(default = "attenuation")]
+        attenuation: [f64; 3],
+    },
+    Spot {
+        position: [f64; 3],
+        direction: [f64; 3],
+        inner_angle: f64,
+        outer_angle: f64,
+        #[serde(default = "attenuation")]
+        attenuation: [f64; 3],
+    },
+}
+
+fn one() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+fn up() -> [f64; 3] {
+    [0., 1., 0.]
+}
+
+fn fov() -> f64 {
+    45.
+}
+
+fn intensity() -> f64 {
+    1.
+}
+
+// full intensity at the light, falling off with the square of the distance
+fn attenuation() -> [f64; 3] {
+    [1., 0., 1.]
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            models: Vec::new(),
+            cameras: Vec::new(),
+            lights: Vec::new(),
+            ambient: cgmath::vec3(0., 0., 0.),
+            shadows: None,
+            ssao: None,
+            sampler: Sampler::default(),
+            msaa: Msaa::Off,
+            supersample: None,
+        }
+    }
+
+    // reads a toml scene file, models and textures are loaded relative to the file
+    pub fn load<P: AsRef<Path>>(filename: P) -> Result<Scene, SceneError> {
+        let filename = filename.as_ref();
+        let directory = filename.parent().unwrap_or_else(|| Path::new(""));
+        let file: SceneFile = toml::from_str(&fs::read_to_string(filename)?)?;
+
+        let mut scene = Scene::new();
+        scene.ambient = file.ambient.into();
+        scene.shadows = file.shadows.map(|entry| {
+            let defaults = ShadowSettings::default();
+            ShadowSettings {
+                size: entry.size.unwrap_or(defaults.size),
+                bias: entry.bias.unwrap_or(defaults.bias),
+                pcf: entry.pcf.unwrap_or(defaults.pcf),
+            }
+        });
+        scene.ssao = file.ssao.map(|entry| {
+            let defaults = SsaoSettings::default();
+            SsaoSettings {
+                directions: entry.directions.unwrap_or(defaults.directions),
+                radius: entry.radius.unwrap_or(defaults.radius),
+                max_distance: entry.max_distance.unwrap_or(defaults.max_distance),
+                strength: entry.strength.unwrap_or(defaults.strength),
+            }
+        });
+        if let Some(entry) = file.sampler {
+            if let Some(filter) = entry.filter {
+                scene.sampler.filter = filter;
+            }
+            if let Some(wrap) = entry.wrap {
+                scene.sampler.wrap = wrap;
+            }
+        }
+        scene.supersample = file.supersample.map(|entry| {
+            let defaults = SupersampleSettings::default();
+            SupersampleSettings {
+                factor: entry.factor.unwrap_or(defaults.factor),
+                filter: entry.filter.unwrap_or(defaults.filter),
+            }
+        });
+        if let Some(MsaaEntry(msaa)) = file.msaa {
+            scene.msaa = msaa;
+        }
+        for entry in file.model {
+            let mut model = SceneModel::new(Model::load(directory.join(&entry.file))?);
+            model.transform = match entry.matrix {
+                // cgmath matrices are built from columns
+                Some(rows) => cgmath::Matrix4::from(rows).transpose(),
+                None => Transform::new(
+                    entry.translate.into(),
+                    entry.rotate.into(),
+                    entry.scale.into(),
+                )
+                .matrix(),
+            };
+            model.diffuse_color = entry.diffuse_color.map(cgmath::Vector3::from);
+            model.diffuse_map = load_texture(directory, entry.diffuse)?;
+            model.normal_map = load_texture(directory, entry.normal)?;
+            if entry.tangent_normal.is_some() {
+                model.normal_map = load_texture(directory, entry.tangent_normal)?;
+                model.normal_space = NormalSpace::Tangent;
+            }
+            model.specular_map = load_texture(directory, entry.specular)?;
+            model.specular_color = entry.specular_color.map(cgmath::Vector3::from);
+            model.wrap = entry.wrap;
+            scene.models.push(model);
+        }
+        for (i, entry) in file.camera.into_iter().enumerate() {
+            let mut camera = Camera::new(
+                entry.position.into(),
+                entry.target.into(),
+                entry.up.into(),
+                entry.fov,
+                1.,
+            );
+            camera.near = entry.near.unwrap_or(camera.near);
+            camera.far = entry.far.unwrap_or(camera.far);
+            if entry.orthographic {
+                camera.projection = Projection::Orthographic;
+            }
+            // unnamed cameras go by their position in the file
+            scene
+                .cameras
+                .push((entry.name.unwrap_or_else(|| i.to_string()), camera));
+        }
+        for entry in file.light {
+            let mut light = match entry.kind {
+                LightKindEntry::Directional { direction } => Light::directional(direction.into()),
+                LightKindEntry::Point {
+                    position,
+                    attenuation,
+                } => Light::point(position.into(), attenuation.into()),
+                LightKindEntry::Spot {
+                    position,
+                    direction,
+                    inner_angle,
+                    outer_angle,
+                    attenuation,
+                } => Light::spot(
+                    position.into(),
+                    direction.into(),
+                    inner_angle,
+                    outer_angle,
+                    attenuation.into(),
+                ),
+            };
+            light.color = entry.color.into();
+            light.intensity = entry.intensity;
+            scene.lights.push(light);
+        }
+        Ok(scene)
+    }
+
+    pub fn get_camera(&self, name: &str) -> Option<&Camera> {
+        self.cameras
+            .iter()
+            .find(|&(camera_name, _)| camera_name == name)
+            .map(|(_, camera)| camera)
+    }
+
+    // renders every model into one image as seen by the camera and darkens the creases with
+    // ssao when it is on. the camera's aspect is replaced by the image's
+    pub fn render(&self, camera: &Camera, width: usize, height: usize) -> Image {
+        self.render_frame(camera, width, height, None).image
+    }
+
+    // render along with the occlusion and stats. the occlusion is worked out with the scene's
+    // ssao settings, or with ssao when the scene has none in which case it doesn't darken the
+    // image. with supersampling on everything happens at the larger size before the image and
+    // occlusion are shrunk
+    pub fn render_frame(
+        &self,
+        camera: &Camera,
+        width: usize,
+        height: usize,
+        ssao: Option<&SsaoSettings>,
+    ) -> Frame {
+        let factor = self.supersample_factor();
+        let (width, height) = (width * factor, height * factor);
+        let camera = frame_camera(camera, width, height);
+        // converts the clip coords to x,y screen coordinates and a zbuffer depth. pixels are
+        // sampled at their integer coords, so the pixels that shrink into a final pixel are
+        // centered (factor - 1) / 2 pixels past the point it would have been sampled at. moving
+        // the image over by as much lines the two up
+        let offset = (factor - 1) as f64 / 2.;
+        let viewport = gl::viewport(offset, offset, width as f64, height as f64, DEPTH);
+        let (buffer, stats) = self.draw(&camera, &viewport, width, height);
+
+        let mut image = buffer.resolve();
+        let occlusion = self.ssao.as_ref().or(ssao).map(|settings| {
+            let unproject = (viewport * camera.projection())
+                .invert()
+                .unwrap_or_else(cgmath::Matrix4::identity);
+            let zbuffer = buffer.resolve_depth();
+            let settings = settings.scaled(factor);
+            ssao::occlusion(&zbuffer, width, height, &unproject, &settings)
+        });
+        if let (Some(_), Some(occlusion)) = (self.ssao, &occlusion) {
+            ssao::apply(&mut image, occlusion);
+        }
+        Frame {
+            image: self.resolve(image),
+            occlusion: occlusion
+                .map(|occlusion| self.resolve(ssao::to_image(&occlusion, width, height))),
+            stats,
+        }
+    }
+
+    // how many times larger than the final image frames are rendered, 1 without supersampling
+    fn supersample_factor(&self) -> usize {
+        self.supersample
+            .map_or(1, |settings| settings.factor.max(1))
+    }
+
+    // shrinks an image rendered supersample_factor times larger down to its final size, it is
+    // returned as is without supersampling
+    fn resolve(&self, image: Image) -> Image {
+        match self.supersample {
+            Some(settings) => supersample::downsample(&image, settings.factor, settings.filter),
+            None => image,
+        }
+    }
+
+    // draws every model into a buffer of width by height pixels, the camera's aspect has to be
+    // the buffer's already
+    fn draw(
+        &self,
+        camera: &Camera,
+        viewport: &cgmath::Matrix4<f64>,
+        width: usize,
+        height: usize,
+    ) -> (SampleBuffer, Stats) {
+        let mut buffer = SampleBuffer::new(width, height, Format::RGB, self.msaa);
+        let mut stats = Stats::default();
+        let view_projection = camera.transform();
+
+        let shadow_maps = self.shadow_maps();
+
+        // stand in for materials without a normal map, pointing straight out of the surface.
+        // that's only true in tangent space, in object space it would light every face as if it
+        // faced +z
+        let mut flat_normal = Image::new(1, 1, Format::RGB);
+        flat_normal.set(0, 0, Color::new(128, 128, 255, 255));
+        let flat_normal = Texture::new(flat_normal);
+
+        for object in &self.models {
+            // Model * View * Projection, takes model coords to clip coords
+            let transform = view_projection * object.transform;
+            let normal_matrix = transform::normal_matrix(&object.transform);
+
+            for (material, faces) in object.model.groups() {
+                // stand in for materials without a diffuse map, filled with the material's color
+                let color = object
+                    .diffuse_color
+                    .or_else(|| material.map(|m| m.diffuse_color))
+                    .unwrap_or_else(|| cgmath::vec3(1., 1., 1.))
+                    * 255.;
+                let mut plain = Image::new(1, 1, Format::RGB);
+                plain.set(
+                    0,
+                    0,
+                    Color::new(color.x as u8, color.y as u8, color.z as u8, 255),
+                );
+                let plain = Texture::new(plain);
+                let diffuse = object
+                    .diffuse_map
+                    .as_ref()
+                    .or_else(|| material.and_then(|m| m.diffuse_map.as_ref()))
+                    .unwrap_or(&plain);
+                let (normal, normal_space) = object
+                    .normal_map
+                    .as_ref()
+                    .map(|map| (map, object.normal_space))
+                    .or_else(|| {
+                        material
+                            .and_then(|m| m.normal_map.as_ref().map(|map| (map, m.normal_space)))
+                    })
+                    .unwrap_or((&flat_normal, NormalSpace::Tangent));
+
+                let specular = object
+                    .specular_map
+                    .as_ref()
+                    .or_else(|| material.and_then(|m| m.shininess_map.as_ref()));
+                let specular_color_map = material.and_then(|m| m.specular_map.as_ref());
+
+                let mut shader = PhongShader::new(
+                    &object.model,
+                    transform,
+                    self.lights.clone(),
+                    diffuse,
+                    normal,
+                );
+                shader.model_matrix = object.transform;
+                shader.normal_matrix = normal_matrix;
+                shader.shadows = shadow_maps.iter().map(Option::as_ref).collect();
+                shader.ambient = self.ambient;
+                shader.eye = camera.position;
+                shader.sampler = Sampler {
+                    wrap: object.wrap.unwrap_or(self.sampler.wrap),
+                    ..self.sampler
+                };
+                shader.normal_space = normal_space;
+                shader.specular = specular;
+                shader.specular_color_map = specular_color_map;
+                // specular maps are there to be seen, black highlights would hide them
+                let highlight = if specular.is_some() || specular_color_map.is_some() {
+                    1.
+                } else {
+                    0.
+                };
+                shader.specular_color = object
+                    .specular_color
+                    .or_else(|| material.and_then(|m| m.specular_color))
+                    .unwrap_or_else(|| cgmath::vec3(highlight, highlight, highlight));
+                if let Some(material) = material {
+                    shader.shininess = material.shininess;
+                }
+                stats += gl::draw_multisampled(faces, &mut shader, viewport, &mut buffer);
+            }
+        }
+        (buffer, stats)
+    }
+
+    // the first of the two passes shadows take, every model is drawn into the shadow map of each
+    // light that can have one
+    fn shadow_maps(&self) -> Vec<Option<ShadowMap>> {
+        let settings = match self.shadows {
+            Some(settings) => settings,
+            None => return Vec::new(),
+        };
+        let (center, radius) = self.bounds();
+        self.lights
+            .iter()
+            .map(|light| {
+                let mut shadow_map = ShadowMap::new(light, center, radius, settings)?;
+                for object in &self.models {
+                    shadow_map.draw(&object.model, object.transform);
+                }
+                Some(shadow_map)
+            })
+            .collect()
+    }
+
+    // a sphere around every model in world coords, as the center and radius. the center of the
+    // bounding box is close enough to the smallest sphere for fitting shadow maps
+    pub fn bounds(&self) -> (cgmath::Vector3<f64>, f64) {
+        let mut min = cgmath::vec3(f64::MAX, f64::MAX, f64::MAX);
+        let mut max = cgmath::vec3(f64::MIN, f64::MIN, f64::MIN);
+        let mut points = Vec::new();
+        for object in &self.models {
+            for vertex in &object.model.vertices {
+                let point = (object.transform * vertex.extend(1.)).truncate();
+                for i in 0..3 {
+                    min[i] = min[i].min(point[i]);
+                    max[i] = max[i].max(point[i]);
+                }
+                points.push(point);
+            }
+        }
+        if points.is_empty() {
+            return (cgmath::vec3(0., 0., 0.), 1.);
+        }
+        let center = (min + max) / 2.;
+        let radius = points
+            .iter()
+            .map(|point| (point - center).magnitude())
+            .fold(0., f64::max);
+        (center, radius.max(1e-6))
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Scene {
+        Scene::new()
+    }
+}
+
+// the camera as it renders an image of the given size
+fn frame_camera(camera: &Camera, width: usize, height: usize) -> Camera {
+    let mut camera = *camera;
+    camera.aspect = width as f64 / height as f64;
+    camera
+}
+
+fn load_texture(directory: &Path, filename: Option<String>) -> Result<Option<Texture>, SceneError> {
+    match filename {
+        Some(filename) => {
+            let path = directory.join(filename);
+            Image::read_tga(&path)
+                .map(|image| Some(Texture::new(image)))
+                .map_err(|err| SceneError::Texture(path.display().to_string(), err))
+        }
+        None => Ok(None),
+    }
+}
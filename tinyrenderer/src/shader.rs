@@ -0,0 +1,544 @@
+use cgmath;
+use cgmath::{InnerSpace, SquareMatrix};
+use image::Color;
+use light;
+use light::Light;
+use obj::{Face, Model, NormalSpace};
+use shadow::ShadowMap;
+use texture::{Sampler, Texture};
+
+// the programmable part of the pipeline. vertex is called for each corner of a face before it is
+// rasterized and fragment for every pixel the face covers, so anything fragment needs from the
+// vertices (uvs, normals, intensities...) has to be stored on the shader by vertex
+pub trait Shader {
+    // returns the clip coords of the nthvert corner of the face
+    fn vertex(&mut self, face: &Face, nthvert: usize) -> cgmath::Vector4<f64>;
+    // returns the color of the pixel at the barycentric coords of the last face passed to
+    // vertex, or None to discard the pixel. the coords are perspective correct, so varyings can
+    // be interpolated with them directly
+    fn fragment(
+        &self,
+        barycentric: cgmath::Vector3<f64>,
+        derivatives: Derivatives,
+    ) -> Option<Color>;
+}
+
+// how much the barycentric coords passed to fragment change from the pixel to the next one to the
+// right (dx) and to the next one up (dy). interpolating the varyings with them gives how much the
+// varyings change, texture lookups need that for uvs to know how many texels end up in a pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Derivatives {
+    pub dx: cgmath::Vector3<f64>,
+    pub dy: cgmath::Vector3<f64>,
+}
+
+// looks up the uv of the nthvert corner of the face, faces without texture coords get 0, 0
+fn texture_coord(model: &Model, face: &Face, nthvert: usize) -> cgmath::Vector2<f64> {
+    match face.texture_indices.get(nthvert) {
+        Some(&index) => model.get_texture_coord(index).truncate(),
+        None => cgmath::vec2(0., 0.),
+    }
+}
+
+fn interpolate<V: cgmath::VectorSpace<Scalar = f64>>(
+    values: &[V; 3],
+    barycentric: cgmath::Vector3<f64>,
+) -> V {
+    values[0] * barycentric.x + values[1] * barycentric.y + values[2] * barycentric.z
+}
+
+// the uv at the barycentric coords and how it changes towards the next pixels, for a sampler
+fn texture_lookup(
+    uvs: &[cgmath::Vector2<f64>; 3],
+    barycentric: cgmath::Vector3<f64>,
+    derivatives: Derivatives,
+) -> [cgmath::Vector2<f64>; 3] {
+    [
+        interpolate(uvs, barycentric),
+        interpolate(uvs, derivatives.dx),
+        interpolate(uvs, derivatives.dy),
+    ]
+}
+
+// samples the texture at the uv and derivatives from texture_lookup
+fn sample(
+    sampler: &Sampler,
+    texture: &Texture,
+    lookup: &[cgmath::Vector2<f64>; 3],
+) -> cgmath::Vector4<f64> {
+    sampler.sample(texture, lookup[0], lookup[1], lookup[2])
+}
+
+// reads a normal out of a normal map color, each channel goes from [0, 1] to [-1, 1]
+fn decode_normal(color: cgmath::Vector4<f64>) -> cgmath::Vector3<f64> {
+    color.truncate() * 2. - cgmath::vec3(1., 1., 1.)
+}
+
+// scales each channel of the rgba color in the range [0, 1] by the matching channel of the rgb
+// light and adds the rgb highlight, which is already in the range [0, 255]. anything brighter
+// than 255 is clamped
+fn shade(
+    color: cgmath::Vector4<f64>,
+    light: cgmath::Vector3<f64>,
+    highlight: cgmath::Vector3<f64>,
+) -> Color {
+    Color::new(
+        (color.x * 255. * light.x + highlight.x) as u8,
+        (color.y * 255. * light.y + highlight.y) as u8,
+        (color.z * 255. * light.z + highlight.z) as u8,
+        (color.w * 255.) as u8,
+    )
+}
+
+// textures the face with the diffuse map and lights it with a single color worked out from the
+// face normal at the middle of the face, faces that no light reaches the front of are discarded
+#[derive(Clone)]
+pub struct FlatShader<'a> {
+    pub model: &'a Model,
+    // takes model coords to clip coords
+    pub transform: cgmath::Matrix4<f64>,
+    // takes model coords to world coords, identity by default
+    pub model_matrix: cgmath::Matrix4<f64>,
+    // takes model normals to world normals, see transform::normal_matrix. identity by default
+    pub normal_matrix: cgmath::Matrix3<f64>,
+    pub lights: Vec<Light>,
+    pub diffuse: &'a Texture,
+    // how the textures are looked up, Sampler::default() by default
+    pub sampler: Sampler,
+    varying_uv: [cgmath::Vector2<f64>; 3],
+    light: cgmath::Vector3<f64>,
+    lit: bool,
+}
+
+impl<'a> FlatShader<'a> {
+    pub fn new(
+        model: &'a Model,
+        transform: cgmath::Matrix4<f64>,
+        lights: Vec<Light>,
+        diffuse: &'a Texture,
+    ) -> FlatShader<'a> {
+        FlatShader {
+            model,
+            transform,
+            model_matrix: cgmath::Matrix4::identity(),
+            normal_matrix: cgmath::Matrix3::identity(),
+            lights,
+            diffuse,
+            sampler: Sampler::default(),
+            varying_uv: [cgmath::vec2(0., 0.); 3],
+            light: cgmath::vec3(0., 0., 0.),
+            lit: false,
+        }
+    }
+}
+
+impl<'a> Shader for FlatShader<'a> {
+    fn vertex(&mut self, face: &Face, nthvert: usize) -> cgmath::Vector4<f64> {
+        if nthvert == 0 {
+            // normalize the cross product of the two sides of the current triangle and light
+            // the whole triangle the way its middle is lit
+            let model_coords: Vec<cgmath::Vector3<f64>> = face
+                .vertices
+                .iter()
+                .map(|&vertex| *self.model.get_vertex(vertex))
+                .collect();
+            // counter clockwise faces point towards the side they are seen from
+            let n = (self.normal_matrix
+                * (model_coords[1] - model_coords[0]).cross(model_coords[2] - model_coords[0]))
+            .normalize();
+            let center = (model_coords[0] + model_coords[1] + model_coords[2]) / 3.;
+            let center = (self.model_matrix * center.extend(1.)).truncate();
+            self.lit = self
+                .lights
+                .iter()
+                .any(|light| n.dot(light.illuminate(center).0) >= 0.);
+            self.light = light::diffuse(&self.lights, center, n);
+        }
+        self.varying_uv[nthvert] = texture_coord(self.model, face, nthvert);
+        let vertex = *self.model.get_vertex(face.vertices[nthvert]);
+        self.transform * vertex.extend(1.)
+    }
+
+    fn fragment(
+        &self,
+        barycentric: cgmath::Vector3<f64>,
+        derivatives: Derivatives,
+    ) -> Option<Color> {
+        if !self.lit {
+            return None;
+        }
+        let uv = texture_lookup(&self.varying_uv, barycentric, derivatives);
+        // grab the pixel color from the diffuse map and light it
+        let mut color = shade(
+            sample(&self.sampler, self.diffuse, &uv),
+            self.light,
+            cgmath::vec3(0., 0., 0.),
+        );
+        // fix alpha
+        color.bgra[3] = 255;
+        Some(color)
+    }
+}
+
+// textures the face with the diffuse map and lights every pixel with the normal map, the light
+// from every one of the lights is added up
+#[derive(Clone)]
+pub struct NormalMapShader<'a> {
+    pub model: &'a Model,
+    // takes model coords to clip coords
+    pub transform: cgmath::Matrix4<f64>,
+    // takes model coords to world coords, identity by default
+    pub model_matrix: cgmath::Matrix4<f64>,
+    // takes the model normals in the normal map to world normals, see transform::normal_matrix.
+    // identity by default
+    pub normal_matrix: cgmath::Matrix3<f64>,
+    pub lights: Vec<Light>,
+    pub diffuse: &'a Texture,
+    pub normal: &'a Texture,
+    // how the textures are looked up, Sampler::default() by default
+    pub sampler: Sampler,
+    varying_uv: [cgmath::Vector2<f64>; 3],
+    // world coords of the corners, point and spot lights depend on where the pixel is
+    varying_position: [cgmath::Vector3<f64>; 3],
+}
+
+impl<'a> NormalMapShader<'a> {
+    pub fn new(
+        model: &'a Model,
+        transform: cgmath::Matrix4<f64>,
+        lights: Vec<Light>,
+        diffuse: &'a Texture,
+        normal: &'a Texture,
+    ) -> NormalMapShader<'a> {
+        NormalMapShader {
+            model,
+            transform,
+            model_matrix: cgmath::Matrix4::identity(),
+            normal_matrix: cgmath::Matrix3::identity(),
+            lights,
+            diffuse,
+            normal,
+            sampler: Sampler::default(),
+            varying_uv: [cgmath::vec2(0., 0.); 3],
+            varying_position: [cgmath::vec3(0., 0., 0.); 3],
+        }
+    }
+}
+
+impl<'a> Shader for NormalMapShader<'a> {
+    fn vertex(&mut self, face: &Face, nthvert: usize) -> cgmath::Vector4<f64> {
+        self.varying_uv[nthvert] = texture_coord(self.model, face, nthvert);
+        let vertex = self.model.get_vertex(face.vertices[nthvert]).extend(1.);
+        self.varying_position[nthvert] = (self.model_matrix * vertex).truncate();
+        self.transform * vertex
+    }
+
+    fn fragment(
+        &self,
+        barycentric: cgmath::Vector3<f64>,
+        derivatives: Derivatives,
+    ) -> Option<Color> {
+        // interpolate the vertices w/ barycentric coords to determine the points x,y
+        let uv = texture_lookup(&self.varying_uv, barycentric, derivatives);
+        let position = interpolate(&self.varying_position, barycentric);
+
+        let res = decode_normal(sample(&self.sampler, self.normal, &uv));
+        let res = (self.normal_matrix * res).normalize();
+
+        Some(shade(
+            sample(&self.sampler, self.diffuse, &uv),
+            light::diffuse(&self.lights, position, res),
+            cgmath::vec3(0., 0., 0.),
+        ))
+    }
+}
+
+// only fills the zbuffer, for drawing shadow maps. every pixel it covers comes out white
+#[derive(Clone)]
+pub struct DepthShader<'a> {
+    pub model: &'a Model,
+    // takes model coords to clip coords
+    pub transform: cgmath::Matrix4<f64>,
+}
+
+impl<'a> DepthShader<'a> {
+    pub fn new(model: &'a Model, transform: cgmath::Matrix4<f64>) -> DepthShader<'a> {
+        DepthShader { model, transform }
+    }
+}
+
+impl<'a> Shader for DepthShader<'a> {
+    fn vertex(&mut self, face: &Face, nthvert: usize) -> cgmath::Vector4<f64> {
+        let vertex = *self.model.get_vertex(face.vertices[nthvert]);
+        self.transform * vertex.extend(1.)
+    }
+
+    fn fragment(
+        &self,
+        _barycentric: cgmath::Vector3<f64>,
+        _derivatives: Derivatives,
+    ) -> Option<Color> {
+        Some(Color::gray(255))
+    }
+}
+
+// blinn phong shading, ambient and diffuse light tint the diffuse map and the highlights are
+// added on top in the specular color. the normal map lights every pixel, in object space like
+// NormalMapShader or in tangent space, and the specular map holds the exponent of each pixel,
+// falling back to shininess without one. the specular color map tints the highlights of each
+// pixel
+#[derive(Clone)]
+pub struct PhongShader<'a> {
+    pub model: &'a Model,
+    // takes model coords to clip coords
+    pub transform: cgmath::Matrix4<f64>,
+    // takes model coords to world coords, identity by default
+    pub model_matrix: cgmath::Matrix4<f64>,
+    // takes the model normals in the normal map to world normals, see transform::normal_matrix.
+    // identity by default
+    pub normal_matrix: cgmath::Matrix3<f64>,
+    pub lights: Vec<Light>,
+    // the shadow map of each light, lights without one and all of them when it is empty (the
+    // default) aren't shadowed
+    pub shadows: Vec<Option<&'a ShadowMap>>,
+    // rgb light that reaches every pixel, black by default
+    pub ambient: cgmath::Vector3<f64>,
+    // world coords of the camera, highlights move with it. the origin by default
+    pub eye: cgmath::Vector3<f64>,
+    pub diffuse: &'a Texture,
+    pub normal: &'a Texture,
+    // how the textures are looked up, Sampler::default() by default
+    pub sampler: Sampler,
+    // object space by default
+    pub normal_space: NormalSpace,
+    // grayscale, a pixel's value is its exponent and 0 turns its highlights off
+    pub specular: Option<&'a Texture>,
+    // rgb in the range [0, 1], black by default which turns the highlights off
+    pub specular_color: cgmath::Vector3<f64>,
+    // multiplies specular_color, None by default
+    pub specular_color_map: Option<&'a Texture>,
+    // the exponent used without a specular map, 1 by default
+    pub shininess: f64,
+    varying_uv: [cgmath::Vector2<f64>; 3],
+    varying_position: [cgmath::Vector3<f64>; 3],
+    // model coords, only used for tangent space normal maps
+    varying_normal: [cgmath::Vector3<f64>; 3],
+    varying_tangent: [cgmath::Vector3<f64>; 3],
+    varying_bitangent: [cgmath::Vector3<f64>; 3],
+}
+
+impl<'a> PhongShader<'a> {
+    pub fn new(
+        model: &'a Model,
+        transform: cgmath::Matrix4<f64>,
+        lights: Vec<Light>,
+        diffuse: &'a Texture,
+        normal: &'a Texture,
+    ) -> PhongShader<'a> {
+        PhongShader {
+            model,
+            transform,
+            model_matrix: cgmath::Matrix4::identity(),
+            normal_matrix: cgmath::Matrix3::identity(),
+            lights,
+            shadows: Vec::new(),
+            ambient: cgmath::vec3(0., 0., 0.),
+            eye: cgmath::vec3(0., 0., 0.),
+            diffuse,
+            normal,
+            sampler: Sampler::default(),
+            normal_space: NormalSpace::Object,
+            specular: None,
+            specular_color: cgmath::vec3(0., 0., 0.),
+            specular_color_map: None,
+            shininess: 1.,
+            varying_uv: [cgmath::vec2(0., 0.); 3],
+            varying_position: [cgmath::vec3(0., 0., 0.); 3],
+            varying_normal: [cgmath::vec3(0., 0., 0.); 3],
+            varying_tangent: [cgmath::vec3(0., 0., 0.); 3],
+            varying_bitangent: [cgmath::vec3(0., 0., 0.); 3],
+        }
+    }
+}
+
+impl<'a> Shader for PhongShader<'a> {
+    fn vertex(&mut self, face: &Face, nthvert: usize) -> cgmath::Vector4<f64> {
+        self.varying_uv[nthvert] = texture_coord(self.model, face, nthvert);
+        let vertex = self.model.get_vertex(face.vertices[nthvert]).extend(1.);
+        self.varying_position[nthvert] = (self.model_matrix * vertex).truncate();
+        if self.normal_space == NormalSpace::Tangent {
+            self.varying_normal[nthvert] = match face.normals_indices.get(nthvert) {
+                Some(&index) => *self.model.get_vertex_normal(index),
+                None => face_normal(self.model, face),
+            };
+            // without uvs there is no tangent frame and tangent_to_model falls back to the
+            // normal, the frame of the last face drawn must not carry over
+            let (tangent, bitangent) = match face.texture_indices.get(nthvert) {
+                Some(&index) => (
+                    *self.model.get_tangent(index),
+                    *self.model.get_bitangent(index),
+                ),
+                None => (cgmath::vec3(0., 0., 0.), cgmath::vec3(0., 0., 0.)),
+            };
+            self.varying_tangent[nthvert] = tangent;
+            self.varying_bitangent[nthvert] = bitangent;
+        }
+        self.transform * vertex
+    }
+
+    fn fragment(
+        &self,
+        barycentric: cgmath::Vector3<f64>,
+        derivatives: Derivatives,
+    ) -> Option<Color> {
+        let uv = texture_lookup(&self.varying_uv, barycentric, derivatives);
+        let position = interpolate(&self.varying_position, barycentric);
+
+        let mut res = decode_normal(sample(&self.sampler, self.normal, &uv));
+        if self.normal_space == NormalSpace::Tangent {
+            res = tangent_to_model(
+                res,
+                interpolate(&self.varying_normal, barycentric),
+                interpolate(&self.varying_tangent, barycentric),
+                interpolate(&self.varying_bitangent, barycentric),
+            );
+        }
+        let res = (self.normal_matrix * res).normalize();
+
+        // shadows dim each light by how much of it gets past whatever is in the way
+        let shadowed: Vec<Light>;
+        let lights = if self.shadows.is_empty() {
+            &self.lights
+        } else {
+            shadowed = self
+                .lights
+                .iter()
+                .zip(&self.shadows)
+                .map(|(light, shadow)| match *shadow {
+                    Some(shadow) => Light {
+                        intensity: light.intensity * shadow.visibility(position),
+                        ..*light
+                    },
+                    None => *light,
+                })
+                .collect();
+            &shadowed
+        };
+
+        let exponent = match self.specular {
+            Some(specular) => sample(&self.sampler, specular, &uv).z * 255.,
+            None => self.shininess,
+        };
+        let highlight = if exponent > 0. {
+            let view = (self.eye - position).normalize();
+            let highlight = light::specular(lights, position, res, view, exponent);
+            let color = match self.specular_color_map {
+                Some(map) => {
+                    let tint = sample(&self.sampler, map, &uv);
+                    cgmath::vec3(
+                        self.specular_color.x * tint.x,
+                        self.specular_color.y * tint.y,
+                        self.specular_color.z * tint.z,
+                    )
+                }
+                None => self.specular_color,
+            };
+            cgmath::vec3(
+                highlight.x * color.x,
+                highlight.y * color.y,
+                highlight.z * color.z,
+            ) * 255.
+        } else {
+            cgmath::vec3(0., 0., 0.)
+        };
+
+        let light = self.ambient + light::diffuse(lights, position, res);
+        Some(shade(
+            sample(&self.sampler, self.diffuse, &uv),
+            light,
+            highlight,
+        ))
+    }
+}
+
+// the normal of the plane the face lies in, for models without vertex normals
+fn face_normal(model: &Model, face: &Face) -> cgmath::Vector3<f64> {
+    let p0 = *model.get_vertex(face.vertices[0]);
+    (model.get_vertex(face.vertices[1]) - p0)
+        .cross(model.get_vertex(face.vertices[2]) - p0)
+        .normalize()
+}
+
+// takes a normal from a tangent space normal map to model coords. the interpolated frame isn't
+// orthogonal anymore so the tangent is straightened against the normal (gram schmidt) and the
+// bitangent rebuilt from both, keeping the side the uvs put it on so mirrored uvs still work
+fn tangent_to_model(
+    mapped: cgmath::Vector3<f64>,
+    normal: cgmath::Vector3<f64>,
+    tangent: cgmath::Vector3<f64>,
+    bitangent: cgmath::Vector3<f64>,
+) -> cgmath::Vector3<f64> {
+    let normal = normal.normalize();
+    let tangent = tangent - normal * normal.dot(tangent);
+    // no uvs to build the frame from, the surface normal is all there is
+    if tangent.magnitude2() < 1e-12 {
+        return normal;
+    }
+    let tangent = tangent.normalize();
+    let mut side = normal.cross(tangent);
+    if side.dot(bitangent) < 0. {
+        side = -side;
+    }
+    tangent * mapped.x + side * mapped.y + normal * mapped.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Format, Image};
+
+    // a single counter clockwise triangle in the z = 0 plane, facing +z
+    fn triangle() -> Model {
+        Model {
+            vertices: vec![
+                cgmath::vec3(-1., -1., 0.),
+                cgmath::vec3(1., -1., 0.),
+                cgmath::vec3(0., 1., 0.),
+            ],
+            texture_coords: Vec::new(),
+            vertex_normals: Vec::new(),
+            tangents: Vec::new(),
+            bitangents: Vec::new(),
+            faces: vec![Face::new(vec![0, 1, 2], vec![], vec![], None)],
+            materials: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn flat_shading_lights_the_side_the_light_is_on() {
+        let model = triangle();
+        let mut white = Image::new(1, 1, Format::RGB);
+        white.set(0, 0, Color::gray(255));
+        let diffuse = Texture::new(white);
+        let derivatives = Derivatives {
+            dx: cgmath::vec3(0., 0., 0.),
+            dy: cgmath::vec3(0., 0., 0.),
+        };
+        let center = cgmath::vec3(1., 1., 1.) / 3.;
+        for &(z, lit) in &[(5., true), (-5., false)] {
+            let lights = vec![
+                Light::point(cgmath::vec3(0., 0., z), cgmath::vec3(1., 0., 0.)),
+                Light::directional(cgmath::vec3(0., 0., z)),
+            ];
+            for light in lights {
+                let mut shader =
+                    FlatShader::new(&model, cgmath::Matrix4::identity(), vec![light], &diffuse);
+                for nthvert in 0..3 {
+                    shader.vertex(&model.faces[0], nthvert);
+                }
+                assert_eq!(shader.fragment(center, derivatives).is_some(), lit);
+            }
+        }
+    }
+}
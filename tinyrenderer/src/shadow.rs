@@ -0,0 +1,168 @@
+use camera::{Camera, Projection};
+use cgmath;
+use cgmath::InnerSpace;
+use gl;
+use image::{Format, Image};
+use light::{Light, LightKind};
+use obj::Model;
+use shader::DepthShader;
+
+// how shadow maps are rendered and looked up
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowSettings {
+    // width and height of each shadow map
+    pub size: usize,
+    // how much closer to the light than the shadow map a point has to be to get shadowed, in
+    // the shadow map's depth range which goes from 0 at the far plane to 1 at the near plane.
+    // too little and surfaces shadow themselves in stripes (shadow acne), too much and shadows
+    // come loose from whatever casts them
+    pub bias: f64,
+    // percentage closer filtering, the lookup averages a square of (2 * pcf + 1)^2 texels around
+    // the point to soften the edges of the shadows. 0 turns it off
+    pub pcf: usize,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            size: 1024,
+            bias: 0.005,
+            pcf: 1,
+        }
+    }
+}
+
+// the depth of the scene as seen from a light, anything further from the light than what the
+// map holds is in shadow
+pub struct ShadowMap {
+    pub settings: ShadowSettings,
+    // takes world coords to shadow map coords, x and y in texels and z in depth
+    pub transform: cgmath::Matrix4<f64>,
+    light_transform: cgmath::Matrix4<f64>,
+    viewport: cgmath::Matrix4<f64>,
+    zbuffer: Vec<f64>,
+    // the depth pass has to draw somewhere, this ends up as a white mask of what the light sees
+    image: Image,
+}
+
+impl ShadowMap {
+    // an empty shadow map for the light that covers the sphere around center, point lights shine
+    // every way and would need six maps so they don't get one
+    pub fn new(
+        light: &Light,
+        center: cgmath::Vector3<f64>,
+        radius: f64,
+        settings: ShadowSettings,
+    ) -> Option<ShadowMap> {
+        let camera = light_camera(light, center, radius)?;
+        let light_transform = camera.transform();
+        let size = settings.size as f64;
+        let viewport = gl::viewport(0., 0., size, size, 1.);
+        Some(ShadowMap {
+            settings,
+            transform: viewport * light_transform,
+            light_transform,
+            viewport,
+            zbuffer: vec![f64::MIN; settings.size * settings.size],
+            image: Image::new(settings.size, settings.size, Format::Grayscale),
+        })
+    }
+
+    // adds the model to the depth of the scene, model_matrix takes it to world coords
+    pub fn draw(&mut self, model: &Model, model_matrix: cgmath::Matrix4<f64>) {
+        let mut shader = DepthShader::new(model, self.light_transform * model_matrix);
+        gl::draw(
+            &model.faces,
+            &mut shader,
+            &self.viewport,
+            &mut self.zbuffer,
+            &mut self.image,
+        );
+    }
+
+    // how much of the light reaches the point in world coords, from 0 in full shadow to 1 fully
+    // lit. points outside of the map are lit
+    pub fn visibility(&self, point: cgmath::Vector3<f64>) -> f64 {
+        let point = self.transform * point.extend(1.);
+        if point.w <= 0. {
+            return 1.;
+        }
+        let point = (point / point.w).truncate();
+        let size = self.settings.size as i32;
+        let pcf = self.settings.pcf as i32;
+        // the rasterizer samples each pixel at its integer coords so round to the closest one
+        let (x, y) = (point.x.round() as i32, point.y.round() as i32);
+
+        let mut lit = 0;
+        for j in y - pcf..=y + pcf {
+            for i in x - pcf..=x + pcf {
+                // bigger depths are closer to the light
+                if i < 0
+                    || j < 0
+                    || i >= size
+                    || j >= size
+                    || point.z + self.settings.bias >= self.zbuffer[(i + j * size) as usize]
+                {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f64 / ((2 * pcf + 1) * (2 * pcf + 1)) as f64
+    }
+
+    // white wherever the light reaches a surface, handy for checking what the map covers
+    pub fn get_image(&self) -> &Image {
+        &self.image
+    }
+}
+
+// a camera at the light that just fits the sphere around center, so the map's texels and depth
+// range aren't wasted on empty space
+fn light_camera(light: &Light, center: cgmath::Vector3<f64>, radius: f64) -> Option<Camera> {
+    match light.kind {
+        LightKind::Directional { direction } => {
+            // backed off along the light to twice the radius, so the sphere sits between the
+            // near and far planes with its edges at tan(fov / 2) * distance
+            let mut camera = Camera::new(
+                center + direction * radius * 2.,
+                center,
+                up_for(direction),
+                (0.5_f64.atan() * 2.).to_degrees(),
+                1.,
+            );
+            camera.projection = Projection::Orthographic;
+            camera.near = radius;
+            camera.far = radius * 3.;
+            Some(camera)
+        }
+        LightKind::Spot {
+            position,
+            direction,
+            outer_angle,
+            ..
+        } => {
+            let mut camera = Camera::new(
+                position,
+                position + direction,
+                up_for(direction),
+                (outer_angle * 2.).min(170.),
+                1.,
+            );
+            // the near plane goes just in front of the sphere, or close to the light when the
+            // light is inside of it
+            camera.far = (center - position).magnitude() + radius;
+            camera.near = ((camera.far - radius * 2.) * 0.9).max(camera.far / 1000.);
+            Some(camera)
+        }
+        LightKind::Point { .. } => None,
+    }
+}
+
+// any up direction works as long as it isn't parallel to where the camera looks
+fn up_for(direction: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+    if direction.y.abs() > 0.99 {
+        cgmath::vec3(0., 0., 1.)
+    } else {
+        cgmath::vec3(0., 1., 0.)
+    }
+}
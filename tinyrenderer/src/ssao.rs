@@ -0,0 +1,214 @@
+use cgmath;
+use cgmath::InnerSpace;
+use image::{Color, Format, Image};
+use std::f64::consts::PI;
+
+// the sine of the lowest horizon that counts as occluding
+const HORIZON_BIAS: f64 = 0.1;
+
+// screen space ambient occlusion, worked out from nothing but the zbuffer of a finished frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SsaoSettings {
+    // how many directions around each pixel are searched for occluders, with 0 nothing is
+    // occluded
+    pub directions: usize,
+    // how far to search in each direction, in pixels
+    pub radius: usize,
+    // occluders further away than this in eye coords are ignored, so a head doesn't darken the
+    // wall a few meters behind it
+    pub max_distance: f64,
+    // scales the occlusion, 0 turns it off and 1 leaves it as is
+    pub strength: f64,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> SsaoSettings {
+        SsaoSettings {
+            directions: 8,
+            radius: 16,
+            max_distance: 0.3,
+            strength: 1.,
+        }
+    }
+}
+
+impl SsaoSettings {
+    // the same settings for an image factor times as wide and high, so a supersampled frame
+    // searches as far around each pixel as the final image would
+    pub fn scaled(&self, factor: usize) -> SsaoSettings {
+        SsaoSettings {
+            radius: self.radius * factor.max(1),
+            ..*self
+        }
+    }
+}
+
+// returns how much ambient light reaches each pixel, 1 where nothing is in the way and less in
+// creases. unproject takes screen coords and the zbuffer depth back to eye coords, it is the
+// inverse of viewport * projection. pixels nothing was drawn on get 1
+//
+// every direction is searched for the point that rises the highest above the plane the pixel's
+// surface lies in, like the horizon seen from the pixel. the higher the horizon the less of the
+// sky the pixel sees
+pub fn occlusion(
+    zbuffer: &[f64],
+    width: usize,
+    height: usize,
+    unproject: &cgmath::Matrix4<f64>,
+    settings: &SsaoSettings,
+) -> Vec<f64> {
+    let mut result = vec![1.; width * height];
+    if settings.directions == 0 {
+        return result;
+    }
+    let positions: Vec<Option<cgmath::Vector3<f64>>> = (0..width * height)
+        .map(|index| {
+            if zbuffer[index] == f64::MIN {
+                return None;
+            }
+            let (x, y) = ((index % width) as f64, (index / width) as f64);
+            let point = unproject * cgmath::vec4(x, y, zbuffer[index], 1.);
+            Some((point / point.w).truncate())
+        })
+        .collect();
+    let position = |x: i32, y: i32| {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            None
+        } else {
+            positions[x as usize + y as usize * width]
+        }
+    };
+
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let point = match position(x, y) {
+                Some(point) => point,
+                None => continue,
+            };
+            let normal = match surface_normal(point, &position, x, y) {
+                Some(normal) => normal,
+                None => continue,
+            };
+
+            let mut occlusion = 0.;
+            for i in 0..settings.directions {
+                let angle = 2. * PI * i as f64 / settings.directions as f64;
+                let (dx, dy) = (angle.cos(), angle.sin());
+                // the sine of the highest horizon found so far
+                let mut horizon: f64 = 0.;
+                for step in 1..=settings.radius {
+                    let sx = x + (dx * step as f64).round() as i32;
+                    let sy = y + (dy * step as f64).round() as i32;
+                    let sample = match position(sx, sy) {
+                        Some(sample) => sample,
+                        None => continue,
+                    };
+                    let offset = sample - point;
+                    let distance = offset.magnitude();
+                    if distance < 1e-9 || distance > settings.max_distance {
+                        continue;
+                    }
+                    horizon = horizon.max(normal.dot(offset) / distance);
+                }
+                // the neighbouring faces of a low poly model rise a little above each other
+                // even where the surface is smooth, ignore horizons that low
+                occlusion += ((horizon - HORIZON_BIAS) / (1. - HORIZON_BIAS)).max(0.);
+            }
+            let occlusion = occlusion / settings.directions as f64 * settings.strength;
+            result[x as usize + y as usize * width] = (1. - occlusion).max(0.);
+        }
+    }
+    result
+}
+
+// the normal of the surface at the pixel from its neighbours, taking the neighbour on whichever
+// side is closer in depth so the edges of objects don't bend it. it faces the camera, which sits
+// at the origin of eye coords
+fn surface_normal<F>(
+    point: cgmath::Vector3<f64>,
+    position: &F,
+    x: i32,
+    y: i32,
+) -> Option<cgmath::Vector3<f64>>
+where
+    F: Fn(i32, i32) -> Option<cgmath::Vector3<f64>>,
+{
+    let closest = |a: Option<cgmath::Vector3<f64>>, b: Option<cgmath::Vector3<f64>>| match (a, b) {
+        (Some(a), Some(b)) => {
+            if (a - point).magnitude2() <= (b - point).magnitude2() {
+                Some(a - point)
+            } else {
+                Some(point - b)
+            }
+        }
+        (Some(a), None) => Some(a - point),
+        (None, Some(b)) => Some(point - b),
+        (None, None) => None,
+    };
+    let tangent = closest(position(x + 1, y), position(x - 1, y))?;
+    let bitangent = closest(position(x, y + 1), position(x, y - 1))?;
+    let normal = tangent.cross(bitangent);
+    if normal.magnitude2() < 1e-18 {
+        return None;
+    }
+    let normal = normal.normalize();
+    Some(if normal.dot(point) > 0. {
+        -normal
+    } else {
+        normal
+    })
+}
+
+// a grayscale picture of the occlusion, white where nothing is occluded
+pub fn to_image(occlusion: &[f64], width: usize, height: usize) -> Image {
+    let mut image = Image::new(width, height, Format::Grayscale);
+    for y in 0..height {
+        for x in 0..width {
+            let value = occlusion[x + y * width];
+            image.set(x as i32, y as i32, Color::gray((value * 255.) as u8));
+        }
+    }
+    image
+}
+
+// darkens the image by the occlusion
+pub fn apply(image: &mut Image, occlusion: &[f64]) {
+    let width = image.get_width();
+    for y in 0..image.get_height() {
+        for x in 0..width {
+            let value = occlusion[x + y * width];
+            let mut color = image.get(x as i32, y as i32);
+            for channel in color.bgra.iter_mut().take(3) {
+                *channel = (*channel as f64 * value) as u8;
+            }
+            image.set(x as i32, y as i32, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::SquareMatrix;
+
+    #[test]
+    fn no_directions_is_no_occlusion() {
+        // a pit in the middle of a flat floor, which occludes its bottom
+        let (width, height) = (9, 9);
+        let mut zbuffer = vec![0.; width * height];
+        zbuffer[4 + 4 * width] = -3.;
+        let unproject = cgmath::Matrix4::identity();
+        let settings = SsaoSettings {
+            max_distance: 10.,
+            ..SsaoSettings::default()
+        };
+        assert!(occlusion(&zbuffer, width, height, &unproject, &settings)[4 + 4 * width] < 1.);
+
+        let settings = SsaoSettings {
+            directions: 0,
+            ..settings
+        };
+        let result = occlusion(&zbuffer, width, height, &unproject, &settings);
+        assert!(result.iter().all(|&value| value == 1.));
+    }
+}
@@ -0,0 +1,174 @@
+use image::{Color, Image};
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+// how the pixels of a supersampled image are weighed when it is shrunk to its final size, the
+// distances the filters take are in pixels of the final image. scene files pick one with
+// filter = "box", "tent" or "lanczos"
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolveFilter {
+    // the average of the pixels that make up each final pixel, the cheapest but it lets through
+    // the most aliasing
+    Box,
+    // weighs pixels less the further they are from the final pixel's center, reaching as far as
+    // the centers of its neighbours. smooth, a little soft
+    Tent,
+    // a windowed sinc reaching 3 pixels out, the sharpest of the three but its negative lobes
+    // can leave a faint ring along hard edges
+    Lanczos,
+}
+
+impl ResolveFilter {
+    // how far from a final pixel's center the filter reaches
+    fn radius(self) -> f64 {
+        match self {
+            ResolveFilter::Box => 0.5,
+            ResolveFilter::Tent => 1.,
+            ResolveFilter::Lanczos => 3.,
+        }
+    }
+
+    // the weight of a pixel distance away from the center
+    fn weight(self, distance: f64) -> f64 {
+        let distance = distance.abs();
+        match self {
+            ResolveFilter::Box => {
+                if distance <= 0.5 {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            ResolveFilter::Tent => (1. - distance).max(0.),
+            ResolveFilter::Lanczos => {
+                if distance >= 3. {
+                    0.
+                } else {
+                    sinc(distance) * sinc(distance / 3.)
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ResolveFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ResolveFilter, String> {
+        match value {
+            "box" => Ok(ResolveFilter::Box),
+            "tent" => Ok(ResolveFilter::Tent),
+            "lanczos" => Ok(ResolveFilter::Lanczos),
+            _ => Err(format!("unknown resolve filter '{}'", value)),
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0. {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// renders factor times as wide and high as asked for and shrinks the result with the filter,
+// which smooths the edges like msaa but also whatever aliasing the shading and textures have
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SupersampleSettings {
+    // how many times larger the image is rendered in each direction, 1 renders it as is and 0
+    // is taken as 1
+    pub factor: usize,
+    pub filter: ResolveFilter,
+}
+
+impl Default for SupersampleSettings {
+    fn default() -> SupersampleSettings {
+        SupersampleSettings {
+            factor: 2,
+            filter: ResolveFilter::Tent,
+        }
+    }
+}
+
+// shrinks an image factor times in each direction, its size has to be a multiple of factor.
+// the image is filtered along its rows and then its columns, which for these filters is the same
+// as filtering both at once
+pub fn downsample(image: &Image, factor: usize, filter: ResolveFilter) -> Image {
+    let factor = factor.max(1);
+    let (width, height) = (image.get_width(), image.get_height());
+    let (target_width, target_height) = (width / factor, height / factor);
+
+    let columns = weights(width, target_width, factor, filter);
+    let mut rows: Vec<[f64; 4]> = Vec::with_capacity(target_width * height);
+    for y in 0..height as i32 {
+        for taps in &columns {
+            rows.push(sum(taps
+                .iter()
+                .map(|&(x, weight)| (image.get(x as i32, y), weight))));
+        }
+    }
+
+    let mut result = Image::new(target_width, target_height, image.get_format());
+    for (y, taps) in weights(height, target_height, factor, filter)
+        .iter()
+        .enumerate()
+    {
+        for x in 0..target_width {
+            let total = taps.iter().fold([0.; 4], |mut total, &(row, weight)| {
+                for (total, channel) in total.iter_mut().zip(&rows[x + row * target_width]) {
+                    *total += channel * weight;
+                }
+                total
+            });
+            let mut color = Color::default();
+            for (channel, total) in color.bgra.iter_mut().zip(total.iter()) {
+                // lanczos can overshoot either way
+                *channel = total.round().clamp(0., 255.) as u8;
+            }
+            result.set(x as i32, y as i32, color);
+        }
+    }
+    result
+}
+
+// the pixels along a row or column of size pixels that go into each of the target_size pixels
+// it shrinks to, with their weights. taps past the ends of the row are left out and the rest
+// weigh a little more to make up for them
+fn weights(
+    size: usize,
+    target_size: usize,
+    factor: usize,
+    filter: ResolveFilter,
+) -> Vec<Vec<(usize, f64)>> {
+    let scale = factor as f64;
+    let reach = filter.radius() * scale;
+    (0..target_size)
+        .map(|target| {
+            // the final pixel's center, in pixels of the large image
+            let center = (target as f64 + 0.5) * scale - 0.5;
+            let first = (center - reach).ceil().max(0.) as usize;
+            let last = ((center + reach).floor() as usize).min(size - 1);
+            let mut taps: Vec<(usize, f64)> = (first..=last)
+                .map(|i| (i, filter.weight((i as f64 - center) / scale)))
+                .filter(|&(_, weight)| weight != 0.)
+                .collect();
+            let total: f64 = taps.iter().map(|&(_, weight)| weight).sum();
+            for tap in &mut taps {
+                tap.1 /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+fn sum<I: Iterator<Item = (Color, f64)>>(taps: I) -> [f64; 4] {
+    let mut total = [0.; 4];
+    for (color, weight) in taps {
+        for (total, channel) in total.iter_mut().zip(color.bgra.iter()) {
+            *total += *channel as f64 * weight;
+        }
+    }
+    total
+}
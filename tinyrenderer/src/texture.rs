@@ -0,0 +1,323 @@
+use cgmath;
+use cgmath::InnerSpace;
+use image::{Color, Image};
+use std::str::FromStr;
+
+// how the texels around a uv are combined into a color, scene files pick one with
+// filter = "nearest", "bilinear" or "trilinear"
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    // the texel the uv falls in, blocky up close and shimmering far away
+    Nearest,
+    // blends the four texels closest to the uv, smooth up close but still shimmers when the
+    // texture is shrunk on the screen
+    Bilinear,
+    // bilinear lookups in the two mipmaps closest to the size the texture is drawn at, blended
+    // together
+    Trilinear,
+}
+
+// what happens to uvs outside of [0, 1], scene files pick one with wrap = "repeat", "clamp" or
+// "mirror"
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    // the texture tiles
+    Repeat,
+    // the edge texels stretch out forever
+    Clamp,
+    // the texture tiles, flipped every other time so the tiles meet seamlessly
+    Mirror,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Filter, String> {
+        match value {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            "trilinear" => Ok(Filter::Trilinear),
+            _ => Err(format!("unknown filter '{}'", value)),
+        }
+    }
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Wrap, String> {
+        match value {
+            "repeat" => Ok(Wrap::Repeat),
+            "clamp" => Ok(Wrap::Clamp),
+            "mirror" => Ok(Wrap::Mirror),
+            _ => Err(format!("unknown wrap mode '{}'", value)),
+        }
+    }
+}
+
+impl Wrap {
+    // brings a uv coord from anywhere back into the first tile, [0, 1] or [0, 2] for mirrored
+    // tiles which only repeat every other time. huge uvs would overflow once they're turned into
+    // texel coords and lose their fraction long before that, and NaNs or infinities from broken
+    // models have nowhere sensible to go so they go to 0
+    fn reduce(self, coord: f64) -> f64 {
+        if !coord.is_finite() {
+            return 0.;
+        }
+        match self {
+            Wrap::Repeat => coord - coord.floor(),
+            Wrap::Clamp => coord.clamp(0., 1.),
+            Wrap::Mirror => coord - (coord / 2.).floor() * 2.,
+        }
+    }
+
+    // takes a texel coord anywhere to one inside of a row or column of size texels, size can't
+    // be 0
+    fn apply(self, coord: i32, size: i32) -> i32 {
+        match self {
+            Wrap::Repeat => coord.rem_euclid(size),
+            Wrap::Clamp => coord.max(0).min(size - 1),
+            Wrap::Mirror => {
+                let coord = coord.rem_euclid(size * 2);
+                if coord < size {
+                    coord
+                } else {
+                    size * 2 - 1 - coord
+                }
+            }
+        }
+    }
+}
+
+// an image along with its mipmaps, each half the size of the one before down to 1x1. an image
+// without any pixels has no mipmaps
+#[derive(Clone, Debug)]
+pub struct Texture {
+    levels: Vec<Image>,
+}
+
+impl Texture {
+    pub fn new(image: Image) -> Texture {
+        let mut levels = vec![image];
+        loop {
+            let next = {
+                let last = &levels[levels.len() - 1];
+                let (width, height) = (last.get_width(), last.get_height());
+                // an empty image has nothing to shrink, it stays the only level
+                if (width <= 1 && height <= 1) || width == 0 || height == 0 {
+                    break;
+                }
+                downsample(last)
+            };
+            levels.push(next);
+        }
+        Texture { levels }
+    }
+
+    // the full size image the texture was made from
+    pub fn get_image(&self) -> &Image {
+        &self.levels[0]
+    }
+
+    // the mipmap at level, 0 is the full size image
+    pub fn get_level(&self, level: usize) -> &Image {
+        &self.levels[level]
+    }
+
+    pub fn get_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.levels[0].get_width()
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.levels[0].get_height()
+    }
+
+    // the texel at x, y of the mipmap at level as rgba in the range [0, 1], the coords are
+    // wrapped into the image first so any coords are fine. an empty image has no texels to
+    // wrap to and is transparent black everywhere
+    fn texel(&self, level: usize, x: i32, y: i32, wrap: Wrap) -> cgmath::Vector4<f64> {
+        let image = &self.levels[level];
+        if image.get_width() == 0 || image.get_height() == 0 {
+            return cgmath::vec4(0., 0., 0., 0.);
+        }
+        let x = wrap.apply(x, image.get_width() as i32);
+        let y = wrap.apply(y, image.get_height() as i32);
+        to_vector(image.get(x, y))
+    }
+}
+
+impl From<Image> for Texture {
+    fn from(image: Image) -> Texture {
+        Texture::new(image)
+    }
+}
+
+// halves the image, every pixel is the average of the 2x2 pixels it covers. odd sizes round
+// down and the last row or column is folded into the one before it
+fn downsample(image: &Image) -> Image {
+    let (width, height) = (image.get_width(), image.get_height());
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut result = Image::new(half_width, half_height, image.get_format());
+    for y in 0..half_height {
+        for x in 0..half_width {
+            // the pixels of the source this one covers, usually 2x2 but the last row and column
+            // take in the leftovers of odd sizes
+            let xs = x * 2..if x + 1 == half_width {
+                width
+            } else {
+                x * 2 + 2
+            };
+            let ys = y * 2..if y + 1 == half_height {
+                height
+            } else {
+                y * 2 + 2
+            };
+            let mut sum = [0usize; 4];
+            let mut count = 0;
+            for j in ys {
+                for i in xs.clone() {
+                    let color = image.get(i as i32, j as i32);
+                    for (total, channel) in sum.iter_mut().zip(color.bgra.iter()) {
+                        *total += *channel as usize;
+                    }
+                    count += 1;
+                }
+            }
+            let mut color = Color::default();
+            for (channel, total) in color.bgra.iter_mut().zip(sum.iter()) {
+                // rounded to the closest value instead of down so the mipmaps don't darken
+                *channel = ((total + count / 2) / count) as u8;
+            }
+            result.set(x as i32, y as i32, color);
+        }
+    }
+    result
+}
+
+fn to_vector(color: Color) -> cgmath::Vector4<f64> {
+    cgmath::vec4(
+        color.bgra[2] as f64,
+        color.bgra[1] as f64,
+        color.bgra[0] as f64,
+        color.bgra[3] as f64,
+    ) / 255.
+}
+
+// how a texture is looked up, the same texture can be sampled in different ways
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap: Wrap) -> Sampler {
+        Sampler { filter, wrap }
+    }
+
+    // returns the color of the texture at uv as rgba in the range [0, 1]. any uv can be looked
+    // up, the wrap mode decides what lies outside of [0, 1] and every lookup stays inside of
+    // the texture. uv_dx and uv_dy are how much the uv changes from one pixel to the next one
+    // to the right and up, which tells trilinear filtering how many texels end up in a pixel.
+    // the other filters ignore them
+    pub fn sample(
+        &self,
+        texture: &Texture,
+        uv: cgmath::Vector2<f64>,
+        uv_dx: cgmath::Vector2<f64>,
+        uv_dy: cgmath::Vector2<f64>,
+    ) -> cgmath::Vector4<f64> {
+        let uv = cgmath::vec2(self.wrap.reduce(uv.x), self.wrap.reduce(uv.y));
+        match self.filter {
+            Filter::Nearest => {
+                let image = texture.get_image();
+                let x = (uv.x * image.get_width() as f64).floor() as i32;
+                let y = (uv.y * image.get_height() as f64).floor() as i32;
+                texture.texel(0, x, y, self.wrap)
+            }
+            Filter::Bilinear => self.bilinear(texture, 0, uv),
+            Filter::Trilinear => {
+                let level = level_of_detail(texture, uv_dx, uv_dy);
+                let lower = level.floor() as usize;
+                let upper = (lower + 1).min(texture.get_levels() - 1);
+                let t = level - lower as f64;
+                let color = self.bilinear(texture, lower, uv);
+                if t == 0. || upper == lower {
+                    return color;
+                }
+                color * (1. - t) + self.bilinear(texture, upper, uv) * t
+            }
+        }
+    }
+
+    // blends the four texels of the mipmap at level whose centers surround uv
+    fn bilinear(
+        &self,
+        texture: &Texture,
+        level: usize,
+        uv: cgmath::Vector2<f64>,
+    ) -> cgmath::Vector4<f64> {
+        let image = texture.get_level(level);
+        // texel centers sit half way between the integer coords
+        let x = uv.x * image.get_width() as f64 - 0.5;
+        let y = uv.y * image.get_height() as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+
+        let bottom = texture.texel(level, x0, y0, self.wrap) * (1. - tx)
+            + texture.texel(level, x0 + 1, y0, self.wrap) * tx;
+        let top = texture.texel(level, x0, y0 + 1, self.wrap) * (1. - tx)
+            + texture.texel(level, x0 + 1, y0 + 1, self.wrap) * tx;
+        bottom * (1. - ty) + top * ty
+    }
+}
+
+impl Default for Sampler {
+    // nearest and repeat, which looks up the same texels as indexing the image at uv * size
+    fn default() -> Sampler {
+        Sampler::new(Filter::Nearest, Wrap::Repeat)
+    }
+}
+
+// which mipmap fits a pixel, the log2 of how many texels of the full size image the pixel spans
+// along its longer side. 0 when a texel is at least as big as a pixel, fractions lie between
+// two mipmaps
+fn level_of_detail(
+    texture: &Texture,
+    uv_dx: cgmath::Vector2<f64>,
+    uv_dy: cgmath::Vector2<f64>,
+) -> f64 {
+    let size = cgmath::vec2(texture.get_width() as f64, texture.get_height() as f64);
+    let dx = cgmath::vec2(uv_dx.x * size.x, uv_dx.y * size.y);
+    let dy = cgmath::vec2(uv_dy.x * size.x, uv_dy.y * size.y);
+    let texels = dx.magnitude2().max(dy.magnitude2());
+    // half the log2 of the squared length saves a square root, a NaN from broken derivatives
+    // ends up at the full size image
+    let level = texels.log2() / 2.;
+    level.max(0.).min((texture.get_levels() - 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Format;
+
+    #[test]
+    fn empty_images_have_no_mipmaps() {
+        for &(width, height) in &[(0, 0), (0, 5), (5, 0)] {
+            let texture = Texture::new(Image::new(width, height, Format::RGB));
+            assert_eq!(texture.get_levels(), 1);
+            let sampler = Sampler::new(Filter::Trilinear, Wrap::Repeat);
+            let zero = cgmath::vec2(0., 0.);
+            let color = sampler.sample(&texture, cgmath::vec2(0.5, 0.5), zero, zero);
+            assert_eq!(color, cgmath::vec4(0., 0., 0., 0.));
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use cgmath;
+use cgmath::{Matrix, SquareMatrix};
+
+// places a model in the world, it is scaled first, then rotated and then translated
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: cgmath::Vector3<f64>,
+    // degrees around the x, y and z axes, applied in that order
+    pub rotation: cgmath::Vector3<f64>,
+    pub scale: cgmath::Vector3<f64>,
+}
+
+impl Transform {
+    pub fn new(
+        translation: cgmath::Vector3<f64>,
+        rotation: cgmath::Vector3<f64>,
+        scale: cgmath::Vector3<f64>,
+    ) -> Transform {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(
+            cgmath::vec3(0., 0., 0.),
+            cgmath::vec3(0., 0., 0.),
+            cgmath::vec3(1., 1., 1.),
+        )
+    }
+
+    // takes model coords to world coords
+    pub fn matrix(&self) -> cgmath::Matrix4<f64> {
+        cgmath::Matrix4::from_translation(self.translation)
+            * cgmath::Matrix4::from_angle_z(cgmath::Deg(self.rotation.z))
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(self.rotation.y))
+            * cgmath::Matrix4::from_angle_x(cgmath::Deg(self.rotation.x))
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+// the matrix that takes normals along with the points the model matrix moves. normals only stay
+// perpendicular to the surface under the inverse transpose, the model matrix itself would skew
+// them whenever the scale isn't uniform. translation doesn't apply to directions so only the
+// upper 3x3 is used, and a matrix that can't be inverted leaves the normals alone
+pub fn normal_matrix(model: &cgmath::Matrix4<f64>) -> cgmath::Matrix3<f64> {
+    let linear =
+        cgmath::Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+    linear
+        .invert()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or_else(cgmath::Matrix3::identity)
+}